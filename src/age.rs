@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use owo_colors::OwoColorize;
+use serde::Serialize;
+
+use crate::flake_lock::LockFile;
+use crate::info::format_timestamp;
+use crate::{elogln, format_args_colored};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// A length of time, parsed from a number of hours, days, weeks or years
+/// such as `36h`, `90d`, `12w` or `1y`, where days are assumed if there is no unit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct Period {
+    secs: u64,
+}
+
+impl Period {
+    pub const fn days(days: u64) -> Self {
+        Self { secs: days * DAY }
+    }
+}
+
+impl FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (count, unit) = s.split_at(split);
+        let count = count.parse::<u64>().map_err(|_| {
+            format!("expected a number followed by `h`, `d`, `w` or `y`, found `{s}`")
+        })?;
+        let unit = match unit {
+            "h" => 60 * 60,
+            "" | "d" => DAY,
+            "w" => 7 * DAY,
+            "y" => 365 * DAY,
+            _ => {
+                return Err(format!(
+                    "expected a unit of `h`, `d`, `w` or `y`, found `{unit}`"
+                ))
+            }
+        };
+        let secs = count
+            .checked_mul(unit)
+            .ok_or_else(|| format!("expected a shorter period, found `{s}`"))?;
+        Ok(Self { secs })
+    }
+}
+
+/// Rounds down to the largest unit which is appropriate, such as `5 days` or `2 years`.
+impl std::fmt::Display for Period {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (count, unit) = match self.secs {
+            secs if secs < 2 * DAY => (secs / (60 * 60), "hour"),
+            secs if secs < 60 * DAY => (secs / DAY, "day"),
+            secs if secs < 2 * 365 * DAY => (secs / (30 * DAY), "month"),
+            secs => (secs / (365 * DAY), "year"),
+        };
+        let plural = if count == 1 { "" } else { "s" };
+        write!(f, "{count} {unit}{plural}")
+    }
+}
+
+/// Every locked node which has a `lastModified` time, oldest first.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgeReport {
+    now: u64,
+    max_age: Period,
+    max_spread: Period,
+    nodes: Vec<NodeAge>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeAge {
+    pub node: String,
+    /// The flake reference of the node, as it was originally declared.
+    pub source: Option<String>,
+    pub last_modified: u64,
+    pub age: Period,
+    /// Whether the node is older than the maximum age.
+    pub stale: bool,
+    /// The newest node with the same source, if this one is older than it
+    /// by more than the maximum spread.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub behind: Option<Behind>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Behind {
+    pub node: String,
+    pub by: Period,
+}
+
+impl AgeReport {
+    /// Find the age of every node relative to `now`, in seconds since the Unix epoch.
+    pub fn new(lock: &LockFile, now: u64, max_age: Period, max_spread: Period) -> Self {
+        let mut nodes = lock
+            .node_indices()
+            .filter_map(|index| {
+                let node = lock.get_node(index)?;
+                let last_modified = node.locked()?.last_modified()?;
+                let age = Period {
+                    secs: now.saturating_sub(last_modified),
+                };
+                Some(NodeAge {
+                    node: index.to_owned(),
+                    source: node.original().map(|original| original.to_string()),
+                    last_modified,
+                    age,
+                    stale: age > max_age,
+                    behind: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        nodes.sort_by(|a, b| {
+            a.last_modified
+                .cmp(&b.last_modified)
+                .then_with(|| a.node.cmp(&b.node))
+        });
+
+        // Of the newest nodes of each source, the first by name is chosen.
+        let mut newest = HashMap::<String, (String, u64)>::new();
+        for row in &nodes {
+            let Some(source) = &row.source else {
+                continue;
+            };
+            let entry = (row.node.clone(), row.last_modified);
+            newest
+                .entry(source.clone())
+                .and_modify(|newest| {
+                    if row.last_modified > newest.1 {
+                        *newest = entry.clone();
+                    }
+                })
+                .or_insert(entry);
+        }
+        for row in &mut nodes {
+            let Some((node, last_modified)) = row.source.as_ref().map(|source| &newest[source])
+            else {
+                continue;
+            };
+            let by = Period {
+                secs: last_modified - row.last_modified,
+            };
+            if by > max_spread {
+                row.behind = Some(Behind {
+                    node: node.clone(),
+                    by,
+                });
+            }
+        }
+
+        Self {
+            now,
+            max_age,
+            max_spread,
+            nodes,
+        }
+    }
+
+    pub fn nodes(&self) -> &[NodeAge] {
+        &self.nodes
+    }
+}
+
+impl std::fmt::Display for AgeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let headers = ["NODE", "AGE", "LAST MODIFIED", "SOURCE"];
+        let cells = self
+            .nodes
+            .iter()
+            .map(|row| {
+                [
+                    row.node.clone(),
+                    row.age.to_string(),
+                    format_timestamp(row.last_modified),
+                    row.source.clone().unwrap_or_else(|| "-".to_owned()),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let mut widths = headers.map(str::len);
+        for row in &cells {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        f.write_fmt(format_args_colored!(
+            :bold .("{:1$}", headers[0], widths[0]),
+            :bold .("{:>1$}", headers[1], widths[1]),
+            :bold .("{:1$}", headers[2], widths[2]),
+            :bold (headers[3]);
+        ))?;
+        for (row, [node, age, last_modified, source]) in self.nodes.iter().zip(&cells) {
+            let node = format!("{node:0$}", widths[0]);
+            let age = format!("{age:>0$}", widths[1]);
+            if row.stale {
+                f.write_fmt(format_args_colored!((node), :bold :bright_yellow (age)))?
+            } else {
+                f.write_fmt(format_args_colored!((node), :bright_green (age)))?
+            }
+            f.write_fmt(format_args_colored!(, :dimmed .("{last_modified:0$}", widths[2]), ))?;
+            if row.behind.is_some() {
+                f.write_fmt(format_args_colored!(:purple (source);))?
+            } else {
+                f.write_fmt(format_args_colored!(:dimmed (source);))?
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn elog_flagged(report: &AgeReport) {
+    for row in &report.nodes {
+        if row.stale {
+            elogln!(
+                :bold :bright_yellow "warning:", :yellow .("'{}'", row.node),
+                "was last modified", (row.age), "ago"
+            );
+        }
+        if let Some(Behind { node, by }) = &row.behind {
+            elogln!(
+                :bold :bright_yellow "warning:", :yellow .("'{}'", row.node),
+                "is", (by), "older than", :green "'{node}'", "from the same source"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AgeReport, Period, DAY};
+    use crate::flake_lock::LockFile;
+
+    #[test]
+    fn periods() {
+        assert_eq!("90".parse(), Ok(Period::days(90)));
+        assert_eq!("2w".parse(), Ok(Period::days(14)));
+        assert_eq!("1y".parse(), Ok(Period::days(365)));
+        assert!("1m".parse::<Period>().is_err());
+        assert!("99999999999999y".parse::<Period>().is_err());
+        assert_eq!(Period::days(1).to_string(), "24 hours");
+        assert_eq!(Period::days(45).to_string(), "45 days");
+        assert_eq!(Period::days(400).to_string(), "13 months");
+        assert_eq!(Period::days(1000).to_string(), "2 years");
+    }
+
+    #[test]
+    fn flags() {
+        let lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "nixpkgs": {
+                        "locked": { "type": "github", "owner": "o", "repo": "nixpkgs", "lastModified": 864000 },
+                        "original": { "type": "github", "owner": "o", "repo": "nixpkgs" }
+                    },
+                    "nixpkgs_2": {
+                        "locked": { "type": "github", "owner": "o", "repo": "nixpkgs", "lastModified": 0 },
+                        "original": { "type": "github", "owner": "o", "repo": "nixpkgs" }
+                    },
+                    "local": {
+                        "locked": { "type": "path", "path": "/local" },
+                        "original": { "type": "path", "path": "/local" }
+                    },
+                    "root": { "inputs": { "local": "local", "nixpkgs": "nixpkgs", "tool": "nixpkgs_2" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let report = AgeReport::new(&lock, 12 * DAY, Period::days(5), Period::days(7));
+        let rows = report
+            .nodes()
+            .iter()
+            .map(|row| {
+                (
+                    row.node.as_str(),
+                    row.stale,
+                    row.behind.as_ref().map(|behind| behind.node.as_str()),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            [
+                ("nixpkgs_2", true, Some("nixpkgs")),
+                ("nixpkgs", false, None)
+            ]
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use ignore::WalkBuilder;
+use owo_colors::OwoColorize;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::cli_args::{Input, Output};
+use crate::elogln;
+use crate::fmt_colors::capture;
+use crate::output_format::{csv_records, OutputFormat};
+
+static LOCK_FILE_NAME: &str = "flake.lock";
+
+/// Expand the paths given on the command line into the lock files to process.
+///
+/// Directories are replaced with the `flake.lock` inside them, or if `recursive`
+/// is set, with every `flake.lock` beneath them which is not ignored by git.
+/// When no paths are given, the current directory is used.
+pub fn resolve_inputs(mut inputs: Vec<Input>, recursive: bool) -> Result<Vec<Input>, String> {
+    if inputs.is_empty() {
+        inputs.push(Input::from(if recursive { "." } else { "./flake.lock" }));
+    }
+
+    let mut resolved = Vec::new();
+    for input in inputs {
+        let dir = match &input {
+            Input::File(path) if path.is_dir() => path,
+            _ => {
+                resolved.push(input);
+                continue;
+            }
+        };
+        if !recursive {
+            resolved.push(Input::File(dir.join(LOCK_FILE_NAME)));
+            continue;
+        }
+        let mut found = Vec::<PathBuf>::new();
+        for entry in WalkBuilder::new(dir).build() {
+            let entry = entry.map_err(|e| format!("Failed to search `{}`: {e}", dir.display()))?;
+            if entry.file_type().is_some_and(|kind| kind.is_file())
+                && entry.file_name() == LOCK_FILE_NAME
+            {
+                found.push(entry.into_path());
+            }
+        }
+        if found.is_empty() {
+            return Err(format!(
+                "No `{LOCK_FILE_NAME}` was found in `{}`",
+                dir.display()
+            ));
+        }
+        found.sort_unstable();
+        resolved.extend(found.into_iter().map(Input::File));
+    }
+    Ok(resolved)
+}
+
+/// Run `op` on each of the lock files, returning whether it succeeded for all of them.
+///
+/// A single file is processed as if it were the only thing to do.
+/// Several files are processed in parallel, with the logs of each held back
+/// until it has finished, so that they are not interleaved. Afterward, the
+/// message returned by `op` is summarized for every file.
+pub fn for_each_lock(
+    inputs: &[Input],
+    op: impl Fn(&Input) -> Result<String, String> + Sync,
+) -> bool {
+    if let [input] = inputs {
+        return match op(input) {
+            Ok(_) => true,
+            Err(e) => {
+                elogln!(@quiet :bold :red "error:", (e));
+                false
+            }
+        };
+    }
+
+    let queue = Mutex::new(inputs.iter().enumerate());
+    let results = Mutex::new((0..inputs.len()).map(|_| None).collect::<Vec<_>>());
+    let print_lock = Mutex::new(());
+    let threads = std::thread::available_parallelism()
+        .map_or(1, usize::from)
+        .min(inputs.len());
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let Some((i, input)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let (result, captured) = capture(|| op(input));
+                // Nothing is logged for files which succeed quietly.
+                if result.is_err() || !captured.is_empty() {
+                    let _guard = print_lock.lock().unwrap();
+                    elogln!(@quiet :bold :bright_blue "==>", :bold (input), :bold :bright_blue "<==");
+                    captured.print();
+                    if let Err(e) = &result {
+                        elogln!(@quiet :bold :red "error:", (e));
+                    }
+                    elogln!(@quiet);
+                }
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap();
+    let failed = results
+        .iter()
+        .filter(|result| matches!(result, Some(Err(_))))
+        .count();
+    let total = inputs.len();
+    elogln!(:bold :bright_magenta "Processed {total} lock files:");
+    for (input, result) in inputs.iter().zip(results) {
+        match result.expect("every input to have been processed") {
+            Ok(message) => elogln!(:bold :bright_green "  ok", (input), :dimmed (message)),
+            Err(e) => elogln!(@quiet :bold :red "  failed", (input), :dimmed (e)),
+        }
+    }
+    if failed > 0 {
+        elogln!(@quiet :bold :red "{failed} of {total} lock files failed.");
+    }
+    failed == 0
+}
+
+/// The documents which the lock files write to standard output.
+///
+/// With several lock files, each document is held back, and once every file
+/// has been processed, they are written as one document keyed by the path of
+/// each lock, so that the output can still be parsed.
+pub struct Documents {
+    inputs: Vec<String>,
+    format: OutputFormat,
+    pretty: bool,
+    held: Mutex<Vec<(String, Value)>>,
+}
+
+impl Documents {
+    pub fn new(inputs: &[Input], format: OutputFormat, pretty: bool) -> Self {
+        Self {
+            inputs: inputs.iter().map(Input::to_string).collect(),
+            format,
+            pretty,
+            held: Mutex::default(),
+        }
+    }
+
+    /// Write the document of `input` now if it is the only lock file,
+    /// otherwise hold it back until [`Documents::finish`].
+    pub fn write(&self, input: &Input, value: impl Serialize) -> Result<(), String> {
+        if self.inputs.len() <= 1 {
+            return crate::serialize_to_output(
+                value,
+                self.format,
+                Output::Stdout,
+                true,
+                None,
+                self.pretty,
+            );
+        }
+        let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+        self.held.lock().unwrap().push((input.to_string(), value));
+        Ok(())
+    }
+
+    /// Write the documents which were held back, in the order of the lock files.
+    ///
+    /// As CSV, the records of every document are written together instead,
+    /// with a `lock` column for the path of the lock which each came from.
+    pub fn finish(self) -> Result<(), String> {
+        let mut held = self.held.into_inner().unwrap();
+        if held.is_empty() {
+            return Ok(());
+        }
+        held.sort_by_key(|(path, _)| self.inputs.iter().position(|input| input == path));
+        let document = if self.format == OutputFormat::Csv {
+            let mut records = Vec::new();
+            for (path, value) in held {
+                for fields in csv_records(value)? {
+                    let mut record =
+                        Map::from_iter([("lock".to_owned(), Value::from(path.clone()))]);
+                    record.extend(fields);
+                    records.push(Value::Object(record));
+                }
+            }
+            Value::Array(records)
+        } else {
+            Value::Object(held.into_iter().collect())
+        };
+        crate::serialize_to_output(
+            document,
+            self.format,
+            Output::Stdout,
+            true,
+            None,
+            self.pretty,
+        )
+    }
+}
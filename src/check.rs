@@ -0,0 +1,153 @@
+use owo_colors::OwoColorize;
+
+use crate::flake_lock::{LockFile, NodeEdge};
+use crate::flake_nix::InputDecls;
+use crate::{elogln, EXPECT_ROOT_EXIST};
+
+/// A disagreement between the inputs declared by `flake.nix`
+/// and the edges recorded in `flake.lock`.
+#[derive(Clone, Debug)]
+pub enum Mismatch {
+    /// An input is declared, but there is no such edge in the lock.
+    MissingFromLock { path: Vec<String> },
+    /// The root node has an input which is not declared.
+    MissingFromFlake { path: Vec<String> },
+    /// A follows declaration differs from the edge in the lock.
+    FollowsDrift {
+        path: Vec<String>,
+        declared: Option<Vec<String>>,
+        locked: NodeEdge,
+    },
+    /// The declared `flake` attribute differs from that of the locked node.
+    FlakeDrift { path: Vec<String>, declared: bool },
+    /// A follows declaration which `prune` would produce regardless.
+    RedundantFollows { path: Vec<String>, follows: String },
+}
+
+impl Mismatch {
+    /// Whether the lock no longer reflects `flake.nix`,
+    /// as opposed to `flake.nix` merely being more verbose than necessary.
+    pub fn is_drift(&self) -> bool {
+        !matches!(self, Self::RedundantFollows { .. })
+    }
+}
+
+/// Compare the follows and inputs declared in `flake.nix` with the lock.
+pub fn compare_flake_inputs(lock: &LockFile, decls: &InputDecls) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+
+    decls.walk(&mut |path, decl| {
+        let (parent_path, name) = path.split_at(path.len() - 1);
+        let locked = lock
+            .follow_path(parent_path)
+            .and_then(|parent| Some(lock.get_node(parent)?.get_edge(&name[0])?.clone()));
+        let Some(locked) = locked else {
+            // Overrides of inputs beneath an input that follows another
+            // are ignored by Nix, and so never appear in the lock.
+            if parent_path.len() < 2 || decl.follows.is_some() || decl.url.is_some() {
+                mismatches.push(Mismatch::MissingFromLock {
+                    path: path.to_vec(),
+                });
+            }
+            return;
+        };
+
+        let declared = decl.follows_path();
+        match (&declared, &locked) {
+            (Some(declared), NodeEdge::Follows(follows)) if declared == follows => {
+                if let [input, name] = path {
+                    let root = lock.root().expect(EXPECT_ROOT_EXIST);
+                    let input_is_indexed = root
+                        .get_edge(input)
+                        .is_some_and(|edge| matches!(*edge, NodeEdge::Indexed(_)));
+                    if decl.follows.as_ref() == Some(name)
+                        && input_is_indexed
+                        && root.get_edge(name).is_some()
+                    {
+                        mismatches.push(Mismatch::RedundantFollows {
+                            path: path.to_vec(),
+                            follows: name.clone(),
+                        });
+                    }
+                }
+            }
+            (None, NodeEdge::Indexed(_)) => {}
+            // Only top-level inputs are declared with a URL, nested inputs
+            // without a follows are merely overriding their own inputs.
+            (None, NodeEdge::Follows(_)) if path.len() > 1 => {}
+            _ => mismatches.push(Mismatch::FollowsDrift {
+                path: path.to_vec(),
+                declared,
+                locked: locked.clone(),
+            }),
+        }
+
+        if let (Some(flake), NodeEdge::Indexed(index)) = (decl.flake, &locked) {
+            if lock
+                .get_node(index)
+                .is_some_and(|node| node.is_flake() != flake)
+            {
+                mismatches.push(Mismatch::FlakeDrift {
+                    path: path.to_vec(),
+                    declared: flake,
+                });
+            }
+        }
+    });
+
+    if let Some(root) = lock.root() {
+        let mut names = root.iter_edges().map(|(name, _)| name).collect::<Vec<_>>();
+        names.sort_unstable();
+        for name in names {
+            if !decls.0.contains_key(name) {
+                mismatches.push(Mismatch::MissingFromFlake {
+                    path: vec![name.to_owned()],
+                });
+            }
+        }
+    }
+
+    mismatches
+}
+
+pub fn elog_mismatches(mismatches: &[Mismatch]) {
+    for mismatch in mismatches {
+        match mismatch {
+            Mismatch::MissingFromLock { path } => {
+                elogln!(@quiet :bold :bright_red "drift:", :yellow .("'{}'", path.join("/")), "is declared in `flake.nix` but missing from the lock");
+            }
+            Mismatch::MissingFromFlake { path } => {
+                elogln!(@quiet :bold :bright_red "drift:", :yellow .("'{}'", path.join("/")), "is in the lock but not declared in `flake.nix`", :dimmed "(unless it is an implicit registry input)");
+            }
+            Mismatch::FollowsDrift {
+                path,
+                declared: Some(declared),
+                locked,
+            } => {
+                elogln!(@quiet :bold :bright_red "drift:", :yellow .("'{}'", path.join("/")), "is declared to follow", :green .("'{}'", declared.join("/")), "but the lock", (describe_edge(locked)));
+            }
+            Mismatch::FollowsDrift {
+                path,
+                declared: None,
+                locked,
+            } => {
+                elogln!(@quiet :bold :bright_red "drift:", :yellow .("'{}'", path.join("/")), "is not declared to follow anything but the lock", (describe_edge(locked)));
+            }
+            Mismatch::FlakeDrift { path, declared } => {
+                elogln!(@quiet :bold :bright_red "drift:", :yellow .("'{}'", path.join("/")), "is declared with", :green "'flake = {declared}'", "but the lock disagrees");
+            }
+            Mismatch::RedundantFollows { path, follows } => {
+                elogln!(:bold :bright_yellow "redundant:", :yellow .("'{}'", path.join("/")), "follows", :green "'{follows}'", "which `allfollow prune` would do anyway");
+            }
+        }
+    }
+}
+
+pub fn describe_edge(edge: &NodeEdge) -> String {
+    match edge {
+        NodeEdge::Indexed(index) => {
+            format!("references {}", format!("'{index}'").italic().purple())
+        }
+        NodeEdge::Follows(_) => format!("follows {}", format!("'{edge}'").green()),
+    }
+}
@@ -1,5 +1,7 @@
 use std::convert::Infallible;
-use std::fs::File;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
 use std::io::{self, BufReader, BufWriter, Read, StdinLock, StdoutLock, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
@@ -26,7 +28,21 @@ pub enum InputReader<'a> {
 #[derive(Debug)]
 pub enum OutputWriter<'a> {
     Stdout(StdoutLock<'a>),
-    File(BufWriter<File>),
+    File(AtomicFile),
+}
+
+/// A file which is written to a temporary sibling, and then renamed over
+/// its destination by [`AtomicFile::persist`], so that the destination
+/// is never observed partially written. The temporary file is removed
+/// if persisting fails or by [`AtomicFile::discard`], and also if it is
+/// dropped, which does not happen if the process aborts.
+#[derive(Debug)]
+pub struct AtomicFile {
+    writer: BufWriter<File>,
+    temp_path: PathBuf,
+    path: PathBuf,
+    new: bool,
+    backup_suffix: Option<String>,
 }
 
 impl Input {
@@ -39,7 +55,7 @@ impl Input {
         }
     }
 
-    pub fn open(&self) -> io::Result<InputReader> {
+    pub fn open(&self) -> io::Result<InputReader<'_>> {
         match self {
             Self::Stdin => Ok(InputReader::Stdin(io::stdin().lock())),
             Self::File(path) => Ok(InputReader::File(BufReader::new(File::open(path)?))),
@@ -59,21 +75,111 @@ impl Output {
 
     // Create or open a file for writing.
     // Set `new` if you would like to error if the file already exists.
-    pub fn create(&self, new: bool) -> io::Result<OutputWriter> {
+    // Set `backup_suffix` to keep a copy of the file being replaced,
+    // with the suffix appended to its name.
+    pub fn create(&self, new: bool, backup_suffix: Option<&str>) -> io::Result<OutputWriter<'_>> {
         match self {
             Self::Stdout => Ok(OutputWriter::Stdout(io::stdout().lock())),
-            Self::File(path) => Ok(OutputWriter::File(BufWriter::new(
-                File::options()
-                    .write(true)
-                    .create(!new)
-                    .truncate(!new)
-                    .create_new(new)
-                    .open(path)?,
-            ))),
+            Self::File(path) => Ok(OutputWriter::File(AtomicFile::create(
+                path,
+                new,
+                backup_suffix,
+            )?)),
+        }
+    }
+}
+
+impl<'a> OutputWriter<'a> {
+    /// Flush the output, and if it is a file, move it into place.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Stdout(mut lock) => lock.flush(),
+            Self::File(file) => file.persist(),
+        }
+    }
+
+    /// Abandon the output, and if it is a file, remove what was written.
+    pub fn discard(self) -> io::Result<()> {
+        match self {
+            Self::Stdout(_) => Ok(()),
+            Self::File(file) => file.discard(),
+        }
+    }
+}
+
+impl AtomicFile {
+    fn create(path: &Path, new: bool, backup_suffix: Option<&str>) -> io::Result<Self> {
+        if new && path.try_exists()? {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("`{}` already exists", path.display()),
+            ));
+        }
+        let temp_path = with_file_name_affixes(path, ".", &format!(".{}.tmp", std::process::id()));
+        let file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            temp_path,
+            path: path.to_owned(),
+            new,
+            backup_suffix: backup_suffix.map(str::to_owned),
+        })
+    }
+
+    /// Move the file into place, or if that fails, remove the temporary file.
+    pub fn persist(mut self) -> io::Result<()> {
+        let persisted = self.try_persist();
+        if persisted.is_err() {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+        persisted
+    }
+
+    /// Remove the temporary file, leaving the destination untouched.
+    pub fn discard(self) -> io::Result<()> {
+        fs::remove_file(&self.temp_path)
+    }
+
+    fn try_persist(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        match fs::metadata(&self.path) {
+            Ok(metadata) => {
+                fs::set_permissions(&self.temp_path, metadata.permissions())?;
+                if let Some(suffix) = &self.backup_suffix {
+                    fs::copy(&self.path, with_file_name_affixes(&self.path, "", suffix))?;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        if self.new {
+            // Linking fails if the destination has since been created.
+            fs::hard_link(&self.temp_path, &self.path)?;
+            fs::remove_file(&self.temp_path)
+        } else {
+            fs::rename(&self.temp_path, &self.path)
         }
     }
 }
 
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // Fails harmlessly if the file has already been moved into place.
+        let _ = fs::remove_file(&self.temp_path);
+    }
+}
+
+fn with_file_name_affixes(path: &Path, prefix: &str, suffix: &str) -> PathBuf {
+    let mut file_name = OsString::from(prefix);
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
 impl<P: Into<PathBuf>> From<P> for Input {
     fn from(value: P) -> Self {
         Self::from_arg(value)
@@ -86,6 +192,15 @@ impl<P: Into<PathBuf>> From<P> for Output {
     }
 }
 
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Stdin => f.write_str("<stdin>"),
+            Self::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
 impl FromStr for Input {
     type Err = Infallible;
 
@@ -135,3 +250,53 @@ impl<'a> Write for OutputWriter<'a> {
         }
     }
 }
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use super::Output;
+
+    #[test]
+    fn atomic_output() {
+        let dir = std::env::temp_dir().join(format!("allfollow-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("flake.lock");
+        fs::write(&path, "old").unwrap();
+        let output = Output::from(&path);
+
+        // Abandoned writes leave the destination untouched.
+        let mut writer = output.create(false, Some(".bak")).unwrap();
+        writer.write_all(b"partial").unwrap();
+        drop(writer);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        let mut writer = output.create(false, None).unwrap();
+        writer.write_all(b"partial").unwrap();
+        writer.discard().unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        let mut writer = output.create(false, Some(".bak")).unwrap();
+        writer.write_all(b"new").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(
+            fs::read_to_string(dir.join("flake.lock.bak")).unwrap(),
+            "old"
+        );
+
+        assert!(output.create(true, None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cli_args::{Input, Output};
+use crate::edit::parse_input_path;
+
+static CONFIG_FILE_NAME: &str = "allfollow.toml";
+
+/// Settings for a lock, read from `allfollow.toml` beside it.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Inputs of the root's inputs, such as `hyprland/nixpkgs`, which are
+    /// left as they are locked instead of following an input of the root.
+    pub exclude: Vec<String>,
+}
+
+impl Config {
+    /// The path of the configuration for a lock, in the same directory,
+    /// or in the current directory if the lock is read from standard input.
+    pub fn path_for(lock_file: &Input) -> PathBuf {
+        match lock_file {
+            Input::File(path) => path.with_file_name(CONFIG_FILE_NAME),
+            Input::Stdin => PathBuf::from(".").join(CONFIG_FILE_NAME),
+        }
+    }
+
+    /// Read the configuration at `path`, which is empty if there is no file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("Failed to read `{}`: {e}", path.display())),
+        };
+        toml::from_str(&text).map_err(|e| format!("Failed to parse `{}`: {e}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let output = Output::from(path);
+        let mut writer = output
+            .create(false, None)
+            .map_err(|e| format!("Could not write to `{}`: {e}", path.display()))?;
+        if let Err(e) = writer.write_all(text.as_bytes()) {
+            let _ = writer.discard();
+            return Err(format!("Failed while writing `{}`: {e}", path.display()));
+        }
+        writer
+            .finish()
+            .map_err(|e| format!("Failed while writing `{}`: {e}", path.display()))
+    }
+
+    /// The excluded inputs of the configuration for a lock, see [`Config::path_for`],
+    /// or none if `ignore` is set.
+    pub fn exclusions_for(lock_file: &Input, ignore: bool) -> Result<Vec<Vec<String>>, String> {
+        if ignore {
+            return Ok(Vec::new());
+        }
+        Self::load(&Self::path_for(lock_file))?.excluded_paths()
+    }
+
+    /// The excluded inputs, split into input names.
+    pub fn excluded_paths(&self) -> Result<Vec<Vec<String>>, String> {
+        self.exclude
+            .iter()
+            .map(|path| parse_input_path(path))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+
+    #[test]
+    fn parses() {
+        let config: Config = toml::from_str(r#"exclude = ["hyprland/nixpkgs"]"#).unwrap();
+        assert_eq!(
+            config.excluded_paths(),
+            Ok(vec![vec!["hyprland".to_owned(), "nixpkgs".to_owned()]])
+        );
+        assert_eq!(toml::from_str::<Config>(""), Ok(Config::default()));
+        assert!(toml::from_str::<Config>("excluded = []").is_err());
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::str::FromStr;
+
+use owo_colors::OwoColorize;
+
+use crate::flake_lock::LockFile;
+use crate::{format_args_colored, FlakeNodeVisits};
+
+/// The column by which the rows of a [`NodeTable`] are ordered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// Most referenced first.
+    Count,
+    #[default]
+    Name,
+    Source,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "count" => Ok(Self::Count),
+            "name" => Ok(Self::Name),
+            "source" => Ok(Self::Source),
+            _ => Err(format!("expected `count`, `name` or `source`, found `{s}`")),
+        }
+    }
+}
+
+/// Every node of a lock, with how it is referenced.
+#[derive(Clone, Debug)]
+pub struct NodeTable<'a> {
+    rows: Vec<NodeRow<'a>>,
+    root_index: &'a str,
+}
+
+#[derive(Clone, Debug)]
+pub struct NodeRow<'a> {
+    pub index: &'a str,
+    /// How many paths from the root reach the node.
+    pub references: u32,
+    /// How many distinct reachable nodes have an edge to the node.
+    pub parents: usize,
+    /// The length of the shortest path from the root, if it is reachable.
+    pub depth: Option<usize>,
+    /// The flake reference of the node, as it was originally declared.
+    pub source: Option<String>,
+    /// Whether another node has the same source.
+    pub duplicate: bool,
+}
+
+impl<'a> NodeTable<'a> {
+    pub fn new(lock: &'a LockFile, visits: &FlakeNodeVisits<'a>) -> Self {
+        let mut parents = HashMap::<String, BTreeSet<&str>>::new();
+        // Orphans are not counted as the parents of the nodes they reference.
+        for index in lock
+            .node_indices()
+            .filter(|&index| visits.get(index).is_some_and(|&count| count > 0))
+        {
+            let node = lock
+                .get_node(index)
+                .expect("a node to exist with this index");
+            for (_, edge) in node.iter_edges() {
+                if let Some(target) = lock.resolve_edge(&edge) {
+                    parents.entry(target).or_default().insert(index);
+                }
+            }
+        }
+
+        let depths = shortest_depths(lock);
+        let mut sources = HashMap::<String, usize>::new();
+        let mut rows = lock
+            .node_indices()
+            .map(|index| {
+                let source = lock
+                    .get_node(index)
+                    .and_then(|node| node.original().map(|original| original.to_string()));
+                if let Some(source) = &source {
+                    *sources.entry(source.clone()).or_default() += 1;
+                }
+                NodeRow {
+                    index,
+                    references: visits.get(index).copied().unwrap_or(0),
+                    parents: parents.get(index).map_or(0, BTreeSet::len),
+                    depth: depths.get(index).copied(),
+                    source,
+                    duplicate: false,
+                }
+            })
+            .collect::<Vec<_>>();
+        for row in &mut rows {
+            row.duplicate = row
+                .source
+                .as_ref()
+                .is_some_and(|source| sources[source] > 1);
+        }
+
+        let mut table = Self {
+            rows,
+            root_index: lock.root_index(),
+        };
+        table.sort_by(SortKey::Name);
+        table
+    }
+
+    pub fn sort_by(&mut self, key: SortKey) {
+        match key {
+            SortKey::Count => self
+                .rows
+                .sort_by(|a, b| b.references.cmp(&a.references).then(a.index.cmp(b.index))),
+            SortKey::Name => self.rows.sort_by(|a, b| a.index.cmp(b.index)),
+            SortKey::Source => self
+                .rows
+                .sort_by(|a, b| a.source.cmp(&b.source).then(a.index.cmp(b.index))),
+        }
+    }
+
+    /// Keep only the nodes which share their source with another node.
+    pub fn retain_duplicates(&mut self) {
+        self.rows.retain(|row| row.duplicate);
+    }
+
+    /// Keep only the nodes which are not reachable from the root.
+    pub fn retain_orphans(&mut self) {
+        self.rows.retain(|row| row.references == 0);
+    }
+
+    pub fn rows(&self) -> &[NodeRow<'a>] {
+        &self.rows
+    }
+}
+
+/// Breadth-first search from the root, for the depth at which each node is first seen.
+fn shortest_depths(lock: &LockFile) -> HashMap<String, usize> {
+    let mut depths = HashMap::from([(lock.root_index().to_owned(), 0)]);
+    let mut queue = VecDeque::from([lock.root_index().to_owned()]);
+    while let Some(index) = queue.pop_front() {
+        let depth = depths[&index];
+        let Some(node) = lock.get_node(&index) else {
+            continue;
+        };
+        for (_, edge) in node.iter_edges() {
+            if let Some(target) = lock.resolve_edge(&edge) {
+                if !depths.contains_key(&target) {
+                    depths.insert(target.clone(), depth + 1);
+                    queue.push_back(target);
+                }
+            }
+        }
+    }
+    depths
+}
+
+impl std::fmt::Display for NodeTable<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let headers = ["NODE", "REFS", "PARENTS", "DEPTH", "SOURCE"];
+        let cells = self
+            .rows
+            .iter()
+            .map(|row| {
+                [
+                    row.index.to_owned(),
+                    row.references.to_string(),
+                    row.parents.to_string(),
+                    row.depth
+                        .map_or_else(|| "-".to_owned(), |depth| depth.to_string()),
+                    row.source.clone().unwrap_or_else(|| "-".to_owned()),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let mut widths = headers.map(str::len);
+        for row in &cells {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        f.write_fmt(format_args_colored!(
+            :bold .("{:1$}", headers[0], widths[0]),
+            :bold .("{:>1$}", headers[1], widths[1]),
+            :bold .("{:>1$}", headers[2], widths[2]),
+            :bold .("{:>1$}", headers[3], widths[3]),
+            :bold (headers[4]);
+        ))?;
+        for (row, [index, references, parents, depth, source]) in self.rows.iter().zip(&cells) {
+            let index = format!("{index:0$}", widths[0]);
+            let references = format!("{references:>0$}", widths[1]);
+            if row.index == self.root_index {
+                f.write_fmt(format_args_colored!(:dimmed (index), :dimmed (references)))?
+            } else if row.references <= 1 {
+                f.write_fmt(
+                    format_args_colored!(:bold :bright_yellow (index), :dimmed (references)),
+                )?
+            } else {
+                f.write_fmt(format_args_colored!((index), :bold :bright_green (references)))?
+            }
+            f.write_fmt(format_args_colored!(
+                , .("{parents:>0$}", widths[2]), .("{depth:>0$}", widths[3]),
+            ))?;
+            if row.duplicate {
+                f.write_fmt(format_args_colored!(:purple (source);))?
+            } else {
+                f.write_fmt(format_args_colored!(:dimmed (source);))?
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NodeTable;
+    use crate::flake_lock::LockFile;
+    use crate::FlakeNodeVisits;
+
+    #[test]
+    fn rows() {
+        let lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "a": {
+                        "inputs": { "b": "b", "c": ["c"] },
+                        "locked": { "type": "github", "owner": "o", "repo": "a" },
+                        "original": { "type": "github", "owner": "o", "repo": "a" }
+                    },
+                    "b": {
+                        "locked": { "type": "github", "owner": "o", "repo": "c" },
+                        "original": { "type": "github", "owner": "o", "repo": "c" }
+                    },
+                    "c": {
+                        "locked": { "type": "github", "owner": "o", "repo": "c" },
+                        "original": { "type": "github", "owner": "o", "repo": "c" }
+                    },
+                    "d": {
+                        "locked": { "type": "path", "path": "/d" },
+                        "original": { "type": "path", "path": "/d" }
+                    },
+                    "root": { "inputs": { "a": "a", "c": "c" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let visits = FlakeNodeVisits::count_from_index(&lock, lock.root_index());
+        let table = NodeTable::new(&lock, &visits);
+        let rows = table
+            .rows()
+            .iter()
+            .map(|row| {
+                (
+                    row.index,
+                    row.references,
+                    row.parents,
+                    row.depth,
+                    row.duplicate,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            [
+                ("a", 1, 1, Some(1), false),
+                ("b", 1, 1, Some(2), true),
+                ("c", 2, 2, Some(1), true),
+                ("d", 0, 0, None, false),
+                ("root", 1, 0, Some(0), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn orphan_parents() {
+        let lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "a": {
+                        "locked": { "type": "github", "owner": "o", "repo": "a" },
+                        "original": { "type": "github", "owner": "o", "repo": "a" }
+                    },
+                    "orphan": {
+                        "inputs": { "a": "a" },
+                        "locked": { "type": "github", "owner": "o", "repo": "orphan" },
+                        "original": { "type": "github", "owner": "o", "repo": "orphan" }
+                    },
+                    "root": { "inputs": { "a": "a" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let visits = FlakeNodeVisits::count_from_index(&lock, lock.root_index());
+        let table = NodeTable::new(&lock, &visits);
+        let parents = table
+            .rows()
+            .iter()
+            .map(|row| (row.index, row.parents))
+            .collect::<Vec<_>>();
+        assert_eq!(parents, [("a", 1), ("orphan", 0), ("root", 0)]);
+    }
+}
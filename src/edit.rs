@@ -0,0 +1,296 @@
+use crate::flake_lock::{LockFile, NodeEdge};
+use crate::lint::{lint_lock, resolve_edge, Problem};
+
+/// Split an input path such as `hyprland/nixpkgs` into input names.
+pub fn parse_input_path(path: &str) -> Result<Vec<String>, String> {
+    let names = path.split('/').map(str::to_owned).collect::<Vec<_>>();
+    if names.iter().any(String::is_empty) {
+        return Err(format!("The input path '{path}' has an empty input name"));
+    }
+    Ok(names)
+}
+
+/// Make the edge at `path` follow `target`, returning the edge which it replaced.
+pub fn set_follows(
+    lock: &LockFile,
+    path: &[String],
+    target: &[String],
+) -> Result<NodeEdge, String> {
+    if target.starts_with(path) {
+        return Err(format!(
+            "The input '{}' cannot follow itself or its own inputs",
+            path.join("/")
+        ));
+    }
+    let new = NodeEdge::from(target.to_vec());
+    if resolve_edge(lock, &new).is_none() {
+        return Err(format!(
+            "The input '{}' does not resolve to a node",
+            target.join("/")
+        ));
+    }
+    let (owner, name) = edge_owner(lock, path)?;
+    let node = lock
+        .get_node(&owner)
+        .expect("a node to exist with this index");
+    let mut edge = node.get_edge_mut(name).expect("the edge to exist");
+    Ok(std::mem::replace(&mut *edge, new))
+}
+
+/// Give the edge at `path`, which follows another input, a copy of the node
+/// which it follows, returning the edge which it replaced.
+pub fn unfollow(lock: &mut LockFile, path: &[String]) -> Result<NodeEdge, String> {
+    let (owner, name) = edge_owner(lock, path)?;
+    let edge = lock
+        .get_node(&owner)
+        .and_then(|node| node.get_edge(name).map(|edge| edge.clone()))
+        .expect("the edge to exist");
+    let NodeEdge::Follows(follows) = &edge else {
+        return Err(format!(
+            "The input '{}' does not follow another input",
+            path.join("/")
+        ));
+    };
+    let Some(target) = resolve_edge(lock, &edge) else {
+        return Err(format!(
+            "The input '{}' follows '{}', which does not resolve to a node",
+            path.join("/"),
+            follows.join("/")
+        ));
+    };
+
+    let copy = lock
+        .get_node(&target)
+        .map(|node| node.clone())
+        .expect("a node to exist with this index");
+    let index = lock.unused_index(name);
+    lock.insert_node(index.clone(), copy);
+    let node = lock
+        .get_node(&owner)
+        .expect("a node to exist with this index");
+    let mut edge = node.get_edge_mut(name).expect("the edge to exist");
+    Ok(std::mem::replace(&mut *edge, NodeEdge::from(index)))
+}
+
+/// Remove the edge at `path`, returning it.
+pub fn remove_input(lock: &mut LockFile, path: &[String]) -> Result<NodeEdge, String> {
+    let (owner, name) = edge_owner(lock, path)?;
+    let mut node = lock
+        .get_node_mut(&owner)
+        .expect("a node to exist with this index");
+    Ok(node.remove_edge(name).expect("the edge to exist"))
+}
+
+/// Copy the node at `from_path` in `from` into the lock, along with every node
+/// that it depends on, and point the edge at `path` to the copy.
+///
+/// The copies are named after the inputs which reach them, with suffixes
+/// where those are taken. Follows within the copies are replaced by the nodes
+/// which they resolve to, because they name inputs of the other lock.
+/// Returns the edge which was replaced, and the index of every node in `from`
+/// with the index of its copy.
+pub fn graft(
+    lock: &mut LockFile,
+    path: &[String],
+    from: &LockFile,
+    from_path: &[String],
+) -> Result<(NodeEdge, Vec<(String, String)>), String> {
+    let (owner, name) = edge_owner(lock, path)?;
+    let source = from
+        .follow_path(from_path)
+        .filter(|index| from.get_node(index).is_some())
+        .ok_or_else(|| {
+            format!(
+                "The input '{}' does not resolve to a node in the other lock",
+                from_path.join("/")
+            )
+        })?;
+
+    let mut copied = Vec::new();
+    let index = copy_closure(lock, from, &source, name, &mut copied)?;
+    let node = lock
+        .get_node(&owner)
+        .expect("a node to exist with this index");
+    let mut edge = node.get_edge_mut(name).expect("the edge to exist");
+    let old = std::mem::replace(&mut *edge, NodeEdge::from(index));
+    Ok((old, copied))
+}
+
+/// Copy the node `index` of `from` as an input named `name`, unless it was
+/// already copied, returning the index of the copy.
+fn copy_closure(
+    lock: &mut LockFile,
+    from: &LockFile,
+    index: &str,
+    name: &str,
+    copied: &mut Vec<(String, String)>,
+) -> Result<String, String> {
+    if let Some((_, copy)) = copied.iter().find(|(source, _)| source == index) {
+        return Ok(copy.clone());
+    }
+    let mut node = from
+        .get_node(index)
+        .map(|node| node.clone())
+        .expect("a node to exist with this index");
+    let edges = node
+        .iter_edges()
+        .map(|(name, edge)| (name.to_owned(), edge.clone()))
+        .collect::<Vec<_>>();
+    node.clear_edges();
+    let copy = lock.unused_index(name);
+    lock.insert_node(copy.clone(), node);
+    copied.push((index.to_owned(), copy.clone()));
+
+    for (name, edge) in edges {
+        let target = resolve_edge(from, &edge).ok_or_else(|| {
+            format!("The input '{name}' of '{index}' does not resolve to a node in the other lock")
+        })?;
+        let target = copy_closure(lock, from, &target, &name, copied)?;
+        lock.get_node_mut(&copy)
+            .expect("a node to exist with this index")
+            .insert_edge(name, NodeEdge::from(target));
+    }
+    Ok(copy)
+}
+
+/// The index of the node owning the edge at `path`, and the name of the edge.
+fn edge_owner<'a>(lock: &LockFile, path: &'a [String]) -> Result<(String, &'a str), String> {
+    let Some((name, parent)) = path.split_last() else {
+        return Err("The input path is empty".to_owned());
+    };
+    let owner = lock
+        .follow_path(parent)
+        .filter(|index| lock.get_node(index).is_some())
+        .ok_or_else(|| {
+            format!(
+                "The input '{}' does not resolve to a node",
+                parent.join("/")
+            )
+        })?;
+    let node = lock
+        .get_node(&owner)
+        .expect("a node to exist with this index");
+    if node.get_edge(name).is_none() {
+        let owner = if parent.is_empty() {
+            "The root".to_owned()
+        } else {
+            format!("The input '{}'", parent.join("/"))
+        };
+        return Err(format!("{owner} has no input named '{name}'"));
+    }
+    Ok((owner, name))
+}
+
+/// The errors of `lock` which are not among the problems found before editing it.
+pub fn new_errors(lock: &LockFile, before: &[Problem]) -> Vec<Problem> {
+    lint_lock(lock)
+        .into_iter()
+        .filter(|problem| problem.is_error() && !before.contains(problem))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{graft, new_errors, parse_input_path, remove_input, set_follows, unfollow};
+    use crate::flake_lock::{LockFile, NodeEdge};
+    use crate::lint::lint_lock;
+
+    #[test]
+    fn edits() {
+        let mut lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "nixpkgs": {
+                        "locked": { "type": "github", "owner": "o", "repo": "nixpkgs" },
+                        "original": { "type": "github", "owner": "o", "repo": "nixpkgs" }
+                    },
+                    "tool": {
+                        "inputs": { "nixpkgs": ["nixpkgs"] },
+                        "locked": { "type": "github", "owner": "o", "repo": "tool" },
+                        "original": { "type": "github", "owner": "o", "repo": "tool" }
+                    },
+                    "root": { "inputs": { "nixpkgs": "nixpkgs", "tool": "tool" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let before = lint_lock(&lock);
+        let path = parse_input_path("tool/nixpkgs").unwrap();
+        assert!(parse_input_path("tool//nixpkgs").is_err());
+
+        assert!(set_follows(&lock, &path, &["missing".to_owned()]).is_err());
+        assert!(set_follows(&lock, &path, &path).is_err());
+
+        assert_eq!(
+            unfollow(&mut lock, &path),
+            Ok(NodeEdge::from_iter(["nixpkgs"]))
+        );
+        assert_eq!(lock.follow_path(&path).as_deref(), Some("nixpkgs_2"));
+        assert!(unfollow(&mut lock, &path).is_err());
+
+        assert_eq!(
+            set_follows(&lock, &path, &["nixpkgs".to_owned()]),
+            Ok(NodeEdge::from("nixpkgs_2"))
+        );
+        assert!(new_errors(&lock, &before).is_empty());
+
+        remove_input(&mut lock, &["nixpkgs".to_owned()]).unwrap();
+        assert_eq!(new_errors(&lock, &before).len(), 1);
+    }
+
+    #[test]
+    fn grafts() {
+        let mut lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "tool": {
+                        "locked": { "type": "github", "owner": "o", "repo": "tool", "rev": "a" },
+                        "original": { "type": "github", "owner": "o", "repo": "tool" }
+                    },
+                    "utils": {
+                        "locked": { "type": "github", "owner": "o", "repo": "utils" },
+                        "original": { "type": "github", "owner": "o", "repo": "utils" }
+                    },
+                    "root": { "inputs": { "tool": "tool", "utils": "utils" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let from: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "tool_3": {
+                        "inputs": { "utils": ["utils"] },
+                        "locked": { "type": "github", "owner": "o", "repo": "tool", "rev": "b" },
+                        "original": { "type": "github", "owner": "o", "repo": "tool" }
+                    },
+                    "utils_2": {
+                        "locked": { "type": "github", "owner": "o", "repo": "utils", "rev": "b" },
+                        "original": { "type": "github", "owner": "o", "repo": "utils" }
+                    },
+                    "root": { "inputs": { "tool": "tool_3", "utils": "utils_2" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let path = ["tool".to_owned()];
+        let (old, copied) = graft(&mut lock, &path, &from, &path).unwrap();
+        assert_eq!(old, NodeEdge::from("tool"));
+        assert_eq!(
+            copied,
+            [
+                ("tool_3".to_owned(), "tool_2".to_owned()),
+                ("utils_2".to_owned(), "utils_2".to_owned())
+            ]
+        );
+        let utils = lock.follow_path(["tool", "utils"]).unwrap();
+        let utils = lock.get_node(utils).unwrap();
+        assert_eq!(utils.locked().unwrap().rev(), Some("b"));
+    }
+}
@@ -1,7 +1,11 @@
 use std::cell::{Ref, RefCell, RefMut};
-use std::collections::HashMap;
+use std::collections::HashSet;
 
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::flake_ref::FlakeRef;
 
 pub const MAX_SUPPORTED_LOCK_VERSION: u32 = 7;
 pub const MIN_SUPPORTED_LOCK_VERSION: u32 = 5;
@@ -13,7 +17,7 @@ fn default_true() -> bool {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct LockFile {
-    nodes: HashMap<String, RefCell<Node>>,
+    nodes: IndexMap<String, RefCell<Node>>,
     root: String,
     version: u32,
 }
@@ -37,16 +41,16 @@ pub enum Node {
 pub struct LockedNode {
     #[serde(skip_serializing_if = "Clone::clone", default = "default_true")]
     flake: bool,
-    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
-    inputs: HashMap<String, RefCell<NodeEdge>>,
-    locked: serde_json::Value,
-    original: serde_json::Value,
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    inputs: IndexMap<String, RefCell<NodeEdge>>,
+    locked: Map<String, Value>,
+    original: Map<String, Value>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct UnlockedNode {
-    inputs: HashMap<String, RefCell<NodeEdge>>,
+    inputs: IndexMap<String, RefCell<NodeEdge>>,
 }
 
 impl NodeEdge {
@@ -85,18 +89,12 @@ impl std::fmt::Display for NodeEdge {
 
 pub trait NodeEdgeRef<'a> {
     fn index(self) -> Option<Ref<'a, str>>;
-
-    fn path(self) -> Option<Ref<'a, Vec<String>>>;
 }
 
 impl<'a> NodeEdgeRef<'a> for Ref<'a, NodeEdge> {
     fn index(self) -> Option<Ref<'a, str>> {
         Ref::filter_map(self, NodeEdge::index).ok()
     }
-
-    fn path(self) -> Option<Ref<'a, Vec<String>>> {
-        Ref::filter_map(self, NodeEdge::path).ok()
-    }
 }
 
 impl From<&str> for NodeEdge {
@@ -127,44 +125,107 @@ where
 }
 
 impl Node {
-    fn edges(&self) -> &HashMap<String, RefCell<NodeEdge>> {
+    fn edges(&self) -> &IndexMap<String, RefCell<NodeEdge>> {
         match self {
             Self::Locked(LockedNode { inputs, .. }) => inputs,
             Self::Unlocked(UnlockedNode { inputs }) => inputs,
         }
     }
 
-    pub fn iter_edges(&self) -> impl Iterator<Item = (&str, Ref<NodeEdge>)> {
+    pub fn iter_edges(&self) -> impl Iterator<Item = (&str, Ref<'_, NodeEdge>)> {
         self.edges()
             .iter()
             .map(|(name, edge)| (name.as_str(), edge.borrow()))
     }
 
-    pub fn iter_edges_mut(&self) -> impl Iterator<Item = (&str, RefMut<NodeEdge>)> {
+    pub fn iter_edges_mut(&self) -> impl Iterator<Item = (&str, RefMut<'_, NodeEdge>)> {
         self.edges()
             .iter()
             .map(|(name, edge)| (name.as_str(), edge.borrow_mut()))
     }
 
-    pub fn get_edge(&self, name: impl AsRef<str>) -> Option<Ref<NodeEdge>> {
+    pub fn get_edge(&self, name: impl AsRef<str>) -> Option<Ref<'_, NodeEdge>> {
         self.edges().get(name.as_ref()).map(|cell| cell.borrow())
     }
 
-    pub fn get_edge_mut(&self, name: impl AsRef<str>) -> Option<RefMut<NodeEdge>> {
+    pub fn get_edge_mut(&self, name: impl AsRef<str>) -> Option<RefMut<'_, NodeEdge>> {
         self.edges()
             .get(name.as_ref())
             .map(|cell| cell.borrow_mut())
     }
+
+    fn edges_mut(&mut self) -> &mut IndexMap<String, RefCell<NodeEdge>> {
+        match self {
+            Self::Locked(LockedNode { inputs, .. }) => inputs,
+            Self::Unlocked(UnlockedNode { inputs }) => inputs,
+        }
+    }
+
+    pub fn insert_edge(&mut self, name: impl Into<String>, edge: NodeEdge) -> Option<NodeEdge> {
+        insert_in_order(self.edges_mut(), name.into(), edge)
+    }
+
+    pub fn remove_edge(&mut self, name: impl AsRef<str>) -> Option<NodeEdge> {
+        self.edges_mut()
+            .shift_remove(name.as_ref())
+            .map(RefCell::into_inner)
+    }
+
+    pub fn clear_edges(&mut self) {
+        self.edges_mut().clear()
+    }
+
+    pub fn is_flake(&self) -> bool {
+        match self {
+            Self::Locked(LockedNode { flake, .. }) => *flake,
+            Self::Unlocked(_) => true,
+        }
+    }
+
+    pub fn locked(&self) -> Option<FlakeRef<'_>> {
+        match self {
+            Self::Locked(LockedNode { locked, .. }) => Some(FlakeRef::new(locked)),
+            Self::Unlocked(_) => None,
+        }
+    }
+
+    pub fn locked_mut(&mut self) -> Option<&mut Map<String, Value>> {
+        match self {
+            Self::Locked(LockedNode { locked, .. }) => Some(locked),
+            Self::Unlocked(_) => None,
+        }
+    }
+
+    pub fn original(&self) -> Option<FlakeRef<'_>> {
+        match self {
+            Self::Locked(LockedNode { original, .. }) => Some(FlakeRef::new(original)),
+            Self::Unlocked(_) => None,
+        }
+    }
+}
+
+/// Replace the value of an existing key in place, or add a new key, where it
+/// belongs if the keys are sorted as Nix writes them, otherwise at the end.
+fn insert_in_order<T>(map: &mut IndexMap<String, RefCell<T>>, key: String, value: T) -> Option<T> {
+    if let Some(cell) = map.get_mut(&key) {
+        return Some(std::mem::replace(cell.get_mut(), value));
+    }
+    if map.keys().is_sorted() {
+        map.insert_sorted(key, RefCell::new(value));
+    } else {
+        map.insert(key, RefCell::new(value));
+    }
+    None
 }
 
 impl LockFile {
     pub fn new() -> Self {
         static ROOT: &str = "root";
         Self {
-            nodes: HashMap::from_iter([(
+            nodes: IndexMap::from_iter([(
                 ROOT.into(),
                 RefCell::new(Node::Unlocked(UnlockedNode {
-                    inputs: HashMap::new(),
+                    inputs: IndexMap::new(),
                 })),
             )]),
             root: ROOT.into(),
@@ -172,7 +233,7 @@ impl LockFile {
         }
     }
 
-    pub fn root(&self) -> Option<Ref<Node>> {
+    pub fn root(&self) -> Option<Ref<'_, Node>> {
         self.nodes.get(&self.root).map(RefCell::borrow)
     }
 
@@ -184,34 +245,132 @@ impl LockFile {
         self.version
     }
 
+    pub fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
     pub fn node_indices(&self) -> impl Iterator<Item = &str> {
         self.nodes.keys().map(String::as_str)
     }
 
-    pub fn get_node(&self, index: impl AsRef<str>) -> Option<Ref<Node>> {
+    pub fn get_node(&self, index: impl AsRef<str>) -> Option<Ref<'_, Node>> {
         self.nodes.get(index.as_ref()).map(RefCell::borrow)
     }
 
-    pub fn get_node_mut(&self, index: impl AsRef<str>) -> Option<RefMut<Node>> {
+    pub fn get_node_mut(&self, index: impl AsRef<str>) -> Option<RefMut<'_, Node>> {
         self.nodes.get(index.as_ref()).map(RefCell::borrow_mut)
     }
 
+    pub fn insert_node(&mut self, index: impl Into<String>, node: Node) -> Option<Node> {
+        insert_in_order(&mut self.nodes, index.into(), node)
+    }
+
     pub fn remove_node(&mut self, index: impl AsRef<str>) -> Option<Node> {
         self.nodes
-            .remove(index.as_ref())
+            .shift_remove(index.as_ref())
             .map(|cell| cell.into_inner())
     }
 
+    /// The input name, suffixed with a number if a node already has it, as Nix does.
+    pub fn unused_index(&self, name: &str) -> String {
+        (1..)
+            .map(|n| match n {
+                1 => name.to_owned(),
+                n => format!("{name}_{n}"),
+            })
+            .find(|index| !self.nodes.contains_key(index))
+            .expect("an unused index")
+    }
+
     pub fn resolve_edge(&self, edge: &NodeEdge) -> Option<String> {
+        self.resolve_edge_within(edge, self.nodes.len())
+    }
+
+    pub fn follow_path(&self, path: impl IntoIterator<Item = impl AsRef<str>>) -> Option<String> {
+        self.follow_path_within(path, self.nodes.len())
+    }
+
+    // Follows which lead back to themselves would never resolve, so give up
+    // after following more paths than there are nodes.
+    fn resolve_edge_within(&self, edge: &NodeEdge, depth: usize) -> Option<String> {
         match edge {
             NodeEdge::Indexed(index) => Some(index.to_owned()),
-            NodeEdge::Follows(path) => self.follow_path(path),
+            NodeEdge::Follows(path) => self.follow_path_within(path, depth.checked_sub(1)?),
         }
     }
 
-    pub fn follow_path(&self, path: impl IntoIterator<Item = impl AsRef<str>>) -> Option<String> {
+    fn follow_path_within(
+        &self,
+        path: impl IntoIterator<Item = impl AsRef<str>>,
+        depth: usize,
+    ) -> Option<String> {
         path.into_iter().try_fold(self.root.clone(), |index, name| {
-            self.resolve_edge(&*self.get_node(index)?.get_edge(name)?)
+            self.resolve_edge_within(&*self.get_node(index)?.get_edge(name)?, depth)
         })
     }
+
+    /// Visit every input path reachable from the root node, depth-first,
+    /// in order of input names.
+    ///
+    /// The closure receives the path of input names taken from the root,
+    /// the index of the node owning the final edge, the edge itself,
+    /// and the index of the node which that edge resolves to.
+    /// Edges which cannot be resolved are skipped, and edges which lead back
+    /// to a node on the current path are not descended into.
+    ///
+    /// The number of paths grows exponentially with inputs which are shared,
+    /// so unless every path is reported, use [`Self::walk_edges`] instead.
+    pub fn walk_inputs(&self, op: &mut impl FnMut(&[String], &str, &NodeEdge, &str)) {
+        self.walk_from(&self.root, true, op)
+    }
+
+    /// Visit every edge of the nodes reachable from the node `from` once,
+    /// depth-first, in order of input names.
+    ///
+    /// The closure receives the same as for [`Self::walk_inputs`], except that
+    /// a node which was already reached is not descended into again, so each
+    /// edge is visited with only the first path which was taken to it.
+    pub fn walk_edges(&self, from: &str, op: &mut impl FnMut(&[String], &str, &NodeEdge, &str)) {
+        self.walk_from(from, false, op)
+    }
+
+    fn walk_from(
+        &self,
+        from: &str,
+        revisit: bool,
+        op: &mut impl FnMut(&[String], &str, &NodeEdge, &str),
+    ) {
+        // Holds the nodes on the current path if `revisit` is set,
+        // otherwise every node which has been reached.
+        fn recurse(
+            lock: &LockFile,
+            index: &str,
+            path: &mut Vec<String>,
+            seen: &mut HashSet<String>,
+            revisit: bool,
+            op: &mut impl FnMut(&[String], &str, &NodeEdge, &str),
+        ) {
+            let Some(node) = lock.get_node(index) else {
+                return;
+            };
+            let mut edges = node.iter_edges().collect::<Vec<_>>();
+            edges.sort_unstable_by_key(|&(name, _)| name);
+            for (name, edge) in edges {
+                let Some(target) = lock.resolve_edge(&edge) else {
+                    continue;
+                };
+                path.push(name.to_owned());
+                op(path, index, &edge, &target);
+                if seen.insert(target.clone()) {
+                    recurse(lock, &target, path, seen, revisit, op);
+                    if revisit {
+                        seen.remove(&target);
+                    }
+                }
+                path.pop();
+            }
+        }
+        let mut seen = HashSet::from([from.to_owned()]);
+        recurse(self, from, &mut Vec::new(), &mut seen, revisit, op)
+    }
 }
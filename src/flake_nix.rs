@@ -0,0 +1,601 @@
+//! A parser for the subset of the Nix language used to declare the `inputs`
+//! of a `flake.nix`. Everything other than `inputs` is skipped over without
+//! being interpreted, so only the structure of the file must be understood.
+
+use std::collections::BTreeMap;
+
+/// The inputs declared by a `flake.nix`, or those declared for one of its inputs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputDecls(pub BTreeMap<String, InputDecl>);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputDecl {
+    pub url: Option<String>,
+    pub flake: Option<bool>,
+    pub follows: Option<String>,
+    pub inputs: InputDecls,
+    /// Any other string attributes, such as `type`, `owner` and `repo`.
+    pub attrs: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl InputDecl {
+    /// The follows declaration as a path of input names from the root,
+    /// as it would be recorded in the lock file.
+    pub fn follows_path(&self) -> Option<Vec<String>> {
+        self.follows.as_ref().map(|follows| {
+            follows
+                .split('/')
+                .filter(|name| !name.is_empty())
+                .map(str::to_owned)
+                .collect()
+        })
+    }
+
+    fn insert(&mut self, path: &[String], value: Value) {
+        match (path, value) {
+            ([attr], Value::Str(url)) if attr == "url" => self.url = Some(url),
+            ([attr], Value::Bool(flake)) if attr == "flake" => self.flake = Some(flake),
+            ([attr], Value::Str(follows)) if attr == "follows" => self.follows = Some(follows),
+            ([attr, rest @ ..], value) if attr == "inputs" => self.inputs.insert(rest, value),
+            ([attr], Value::Str(string)) => {
+                self.attrs.insert(attr.clone(), string);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl InputDecls {
+    /// Parse the `inputs` of a `flake.nix` from its source text.
+    pub fn parse_flake(source: &str) -> Result<Self, ParseError> {
+        let tokens = Lexer::new(source).tokenize()?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+        };
+        let mut decls = Self::default();
+        for (path, value) in parser.parse_file()? {
+            if let [attr, rest @ ..] = path.as_slice() {
+                if attr == "inputs" {
+                    decls.insert(rest, value);
+                }
+            }
+        }
+        Ok(decls)
+    }
+
+    fn insert(&mut self, path: &[String], value: Value) {
+        match (path, value) {
+            ([], Value::Attrs(bindings)) => {
+                for (path, value) in bindings {
+                    self.insert(&path, value);
+                }
+            }
+            ([name, rest @ ..], value) => {
+                let decl = self.0.entry(name.clone()).or_default();
+                match value {
+                    Value::Attrs(bindings) => {
+                        for (path, value) in bindings {
+                            let path = rest.iter().cloned().chain(path).collect::<Vec<_>>();
+                            decl.insert(&path, value);
+                        }
+                    }
+                    value => decl.insert(rest, value),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Visit every declaration, depth-first, with the input path leading to it.
+    pub fn walk(&self, op: &mut impl FnMut(&[String], &InputDecl)) {
+        fn recurse(
+            decls: &InputDecls,
+            path: &mut Vec<String>,
+            op: &mut impl FnMut(&[String], &InputDecl),
+        ) {
+            for (name, decl) in &decls.0 {
+                path.push(name.clone());
+                op(path, decl);
+                recurse(&decl.inputs, path, op);
+                path.pop();
+            }
+        }
+        recurse(self, &mut Vec::new(), op)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Str(String),
+    Bool(bool),
+    Attrs(Vec<(Vec<String>, Value)>),
+    /// Any expression which is not understood.
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    /// A string literal, or `None` if it contains interpolations.
+    Str(Option<String>),
+    Punct(char),
+    /// Any other operator or literal, such as numbers, paths and URIs.
+    Other,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            chars: source.chars().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.chars.peek() == Some(&expected) {
+            self.next_char();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn tokenize(mut self) -> Result<Vec<Token>, ParseError> {
+        let mut tokens = Vec::new();
+        self.tokenize_until(&mut tokens, false)?;
+        Ok(tokens)
+    }
+
+    /// Lex tokens until the end of input, or if `interpolation` is set,
+    /// until the brace closing the current interpolation has been consumed.
+    /// The tokens of interpolations are discarded, they are never interpreted.
+    fn tokenize_until(
+        &mut self,
+        tokens: &mut Vec<Token>,
+        interpolation: bool,
+    ) -> Result<(), ParseError> {
+        let mut depth = 0_usize;
+        while let Some(&c) = self.chars.peek() {
+            let (line, column) = (self.line, self.column);
+            let kind = match c {
+                c if c.is_whitespace() => {
+                    self.next_char();
+                    continue;
+                }
+                '#' => {
+                    while self.next_char().is_some_and(|c| c != '\n') {}
+                    continue;
+                }
+                '/' => {
+                    self.next_char();
+                    if self.eat('*') {
+                        loop {
+                            match self.next_char() {
+                                Some('*') if self.eat('/') => break,
+                                Some(_) => {}
+                                None => return Err(self.error("unterminated comment")),
+                            }
+                        }
+                        continue;
+                    }
+                    TokenKind::Other
+                }
+                '"' => {
+                    self.next_char();
+                    TokenKind::Str(self.string()?)
+                }
+                '\'' => {
+                    self.next_char();
+                    if !self.eat('\'') {
+                        return Err(self.error("unexpected `'`"));
+                    }
+                    TokenKind::Str(self.indented_string()?)
+                }
+                '$' => {
+                    self.next_char();
+                    if !self.eat('{') {
+                        return Err(self.error("unexpected `$`"));
+                    }
+                    self.tokenize_until(&mut Vec::new(), true)?;
+                    TokenKind::Other
+                }
+                c if c.is_ascii_alphabetic() || c == '_' => {
+                    let mut ident = String::new();
+                    while let Some(&c) = self.chars.peek() {
+                        if c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-') {
+                            ident.push(c);
+                            self.next_char();
+                        } else {
+                            break;
+                        }
+                    }
+                    let mut lookahead = self.chars.clone();
+                    match (lookahead.next(), lookahead.next()) {
+                        // URI literals such as `github:foo/bar`, and relative paths.
+                        (Some(':' | '/'), Some(c)) if !c.is_whitespace() => {
+                            self.other_literal();
+                            TokenKind::Other
+                        }
+                        _ => TokenKind::Ident(ident),
+                    }
+                }
+                '{' | '}' | '[' | ']' | '(' | ')' | '=' | ';' | '.' | ',' | '@' | '?' | ':' => {
+                    self.next_char();
+                    if interpolation {
+                        match c {
+                            '{' => depth += 1,
+                            '}' if depth == 0 => return Ok(()),
+                            '}' => depth -= 1,
+                            _ => {}
+                        }
+                    }
+                    if c == '.' && (self.chars.peek() == Some(&'/') || self.eat('.')) {
+                        self.other_literal();
+                        TokenKind::Other
+                    } else if c == '=' && self.eat('=') {
+                        TokenKind::Other
+                    } else {
+                        TokenKind::Punct(c)
+                    }
+                }
+                _ => {
+                    self.next_char();
+                    TokenKind::Other
+                }
+            };
+            tokens.push(Token { kind, line, column });
+        }
+        if interpolation {
+            Err(self.error("unterminated interpolation"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn other_literal(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() || matches!(c, ';' | '{' | '}' | '[' | ']' | '(' | ')' | '"') {
+                break;
+            }
+            self.next_char();
+        }
+    }
+
+    fn string(&mut self) -> Result<Option<String>, ParseError> {
+        let mut string = Some(String::new());
+        loop {
+            match self.next_char() {
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let c = match self.next_char() {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some(c) => c,
+                        None => break,
+                    };
+                    if let Some(s) = &mut string {
+                        s.push(c);
+                    }
+                }
+                Some('$') if self.eat('{') => {
+                    string = None;
+                    self.tokenize_until(&mut Vec::new(), true)?;
+                }
+                Some(c) => {
+                    if let Some(s) = &mut string {
+                        s.push(c);
+                    }
+                }
+                None => break,
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn indented_string(&mut self) -> Result<Option<String>, ParseError> {
+        let mut string = Some(String::new());
+        loop {
+            match self.next_char() {
+                Some('\'') if self.eat('\'') => {
+                    if self.eat('\'') {
+                        if let Some(s) = &mut string {
+                            s.push_str("''");
+                        }
+                    } else if self.eat('$') {
+                        if let Some(s) = &mut string {
+                            s.push('$');
+                        }
+                    } else if self.eat('\\') {
+                        if let (Some(s), Some(c)) = (&mut string, self.next_char()) {
+                            s.push(c);
+                        }
+                    } else {
+                        // Indentation is not stripped, these are not expected in inputs.
+                        return Ok(string);
+                    }
+                }
+                Some('$') if self.eat('{') => {
+                    string = None;
+                    self.tokenize_until(&mut Vec::new(), true)?;
+                }
+                Some(c) => {
+                    if let Some(s) = &mut string {
+                        s.push(c);
+                    }
+                }
+                None => break,
+            }
+        }
+        Err(self.error("unterminated indented string"))
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|token| &token.kind)
+    }
+
+    fn next(&mut self) -> Option<&TokenKind> {
+        let token = self.tokens.get(self.pos).map(|token| &token.kind);
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        let (line, column) = self
+            .tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or((1, 1), |token| (token.line, token.column));
+        ParseError {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn expect(&mut self, punct: char) -> Result<(), ParseError> {
+        if self.peek() == Some(&TokenKind::Punct(punct)) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{punct}`")))
+        }
+    }
+
+    /// The top level of a `flake.nix` must be an attribute set.
+    fn parse_file(&mut self) -> Result<Vec<(Vec<String>, Value)>, ParseError> {
+        let Value::Attrs(bindings) = self.parse_attrs(true)? else {
+            unreachable!()
+        };
+        if self.peek().is_some() {
+            return Err(self.error("expected end of file"));
+        }
+        Ok(bindings)
+    }
+
+    /// Parse an attribute set. Values are only interpreted for bindings
+    /// beneath `inputs`, unless `top_level` is unset.
+    fn parse_attrs(&mut self, top_level: bool) -> Result<Value, ParseError> {
+        if self.peek() == Some(&TokenKind::Ident("rec".into())) {
+            self.pos += 1;
+        }
+        self.expect('{')?;
+        let mut bindings = Vec::new();
+        loop {
+            match self.peek() {
+                Some(TokenKind::Punct('}')) => {
+                    self.pos += 1;
+                    return Ok(Value::Attrs(bindings));
+                }
+                Some(TokenKind::Ident(ident)) if ident == "inherit" => {
+                    self.skip_expr()?;
+                    self.expect(';')?;
+                }
+                Some(_) => {
+                    let path = self.parse_attr_path()?;
+                    self.expect('=')?;
+                    let value = if !top_level || path.first().is_some_and(|a| a == "inputs") {
+                        self.parse_value()?
+                    } else {
+                        self.skip_expr()?;
+                        Value::Other
+                    };
+                    self.expect(';')?;
+                    bindings.push((path, value));
+                }
+                None => return Err(self.error("unterminated attribute set")),
+            }
+        }
+    }
+
+    fn parse_attr_path(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut path = Vec::new();
+        loop {
+            match self.next() {
+                Some(TokenKind::Ident(name)) | Some(TokenKind::Str(Some(name))) => {
+                    path.push(name.clone())
+                }
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error("expected an attribute name"));
+                }
+            }
+            if self.peek() == Some(&TokenKind::Punct('.')) {
+                self.pos += 1;
+            } else {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        let value = match self.peek() {
+            Some(TokenKind::Punct('{')) => return self.parse_attrs(false),
+            Some(TokenKind::Ident(ident)) if ident == "rec" => return self.parse_attrs(false),
+            Some(TokenKind::Str(Some(string))) => Value::Str(string.clone()),
+            Some(TokenKind::Ident(ident)) if ident == "true" => Value::Bool(true),
+            Some(TokenKind::Ident(ident)) if ident == "false" => Value::Bool(false),
+            _ => Value::Other,
+        };
+        self.pos += 1;
+        if self.peek() == Some(&TokenKind::Punct(';')) {
+            Ok(value)
+        } else {
+            // Not a simple literal, such as a string concatenation.
+            self.pos = start;
+            self.skip_expr()?;
+            Ok(Value::Other)
+        }
+    }
+
+    /// Skip over an arbitrary expression, up to but excluding the semicolon
+    /// which terminates the binding that it belongs to.
+    fn skip_expr(&mut self) -> Result<(), ParseError> {
+        let mut depth = 0_usize;
+        // Depths at which a `let`, `with` or `assert` awaits its semicolons.
+        let mut pending = Vec::<(usize, &str)>::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unexpected end of file")),
+                Some(TokenKind::Punct('{' | '[' | '(')) => depth += 1,
+                Some(TokenKind::Punct('}' | ']' | ')')) => match depth.checked_sub(1) {
+                    Some(d) => depth = d,
+                    None => return Ok(()),
+                },
+                Some(TokenKind::Punct(';')) => match pending.last() {
+                    Some(&(d, "let")) if d == depth => {}
+                    Some(&(d, _)) if d == depth => {
+                        pending.pop();
+                    }
+                    _ if depth == 0 => return Ok(()),
+                    _ => {}
+                },
+                Some(TokenKind::Ident(ident)) => match ident.as_str() {
+                    "let" => pending.push((depth, "let")),
+                    "with" => pending.push((depth, "with")),
+                    "assert" => pending.push((depth, "assert")),
+                    "in" if pending.last().is_some_and(|&(_, k)| k == "let") => {
+                        pending.pop();
+                    }
+                    _ => {}
+                },
+                Some(_) => {}
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InputDecls;
+
+    #[test]
+    fn parse_inputs() {
+        let source = r#"
+            {
+              description = "A flake with ${"interpolated"} strings";
+              inputs = {
+                nixpkgs.url = "github:NixOS/nixpkgs/nixpkgs-unstable";
+                rust-overlay = {
+                  url = "github:oxalica/rust-overlay";
+                  inputs.nixpkgs.follows = "nixpkgs";
+                };
+                /* Not a flake. */
+                systems = {
+                  url = "github:nix-systems/default";
+                  flake = false;
+                };
+              };
+              inputs.hyprland.inputs.xdph.inputs."nixpkgs".follows = "nixpkgs";
+              outputs = { self, nixpkgs, ... }@inputs:
+                let
+                  inherit (nixpkgs) lib;
+                  eachSystem = lib.genAttrs (import inputs.systems);
+                in with lib; {
+                  packages = eachSystem (system: { default = ./.; });
+                  str = '' multi ''${line} ${toString 1} '';
+                };
+            }
+        "#;
+        let decls = InputDecls::parse_flake(source).unwrap();
+        let names = decls.0.keys().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(names, ["hyprland", "nixpkgs", "rust-overlay", "systems"]);
+        assert_eq!(
+            decls.0["nixpkgs"].url.as_deref(),
+            Some("github:NixOS/nixpkgs/nixpkgs-unstable")
+        );
+        assert_eq!(decls.0["systems"].flake, Some(false));
+        assert_eq!(
+            decls.0["rust-overlay"].inputs.0["nixpkgs"]
+                .follows
+                .as_deref(),
+            Some("nixpkgs")
+        );
+        let xdph = &decls.0["hyprland"].inputs.0["xdph"];
+        assert_eq!(xdph.inputs.0["nixpkgs"].follows.as_deref(), Some("nixpkgs"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(InputDecls::parse_flake("{ inputs = { a.url = \"x\"; }").is_err());
+        assert!(InputDecls::parse_flake("{ inputs.a.url = \"x\" }").is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use serde_json::{Map, Value};
+
+/// A borrowed, typed view over the attribute set of a flake reference,
+/// such as the `locked` and `original` fields of a node.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlakeRef<'a>(&'a Map<String, Value>);
+
+impl<'a> FlakeRef<'a> {
+    pub fn new(attrs: &'a Map<String, Value>) -> Self {
+        Self(attrs)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&'a str> {
+        self.0.get(key).and_then(Value::as_str)
+    }
+
+    pub fn kind(&self) -> Option<&'a str> {
+        self.get_str("type")
+    }
+
+    pub fn owner(&self) -> Option<&'a str> {
+        self.get_str("owner")
+    }
+
+    pub fn repo(&self) -> Option<&'a str> {
+        self.get_str("repo")
+    }
+
+    pub fn url(&self) -> Option<&'a str> {
+        self.get_str("url")
+    }
+
+    pub fn path(&self) -> Option<&'a str> {
+        self.get_str("path")
+    }
+
+    pub fn id(&self) -> Option<&'a str> {
+        self.get_str("id")
+    }
+
+    pub fn ref_name(&self) -> Option<&'a str> {
+        self.get_str("ref")
+    }
+
+    pub fn rev(&self) -> Option<&'a str> {
+        self.get_str("rev")
+    }
+
+    pub fn last_modified(&self) -> Option<u64> {
+        self.0.get("lastModified").and_then(Value::as_u64)
+    }
+
+    fn write_query(&self, f: &mut std::fmt::Formatter, keys: &[&str]) -> std::fmt::Result {
+        let mut sep = '?';
+        for key in keys {
+            match self.0.get(*key) {
+                Some(Value::String(value)) => write!(f, "{sep}{key}={value}")?,
+                Some(Value::Bool(true)) => write!(f, "{sep}{key}=1")?,
+                Some(Value::Number(value)) => write!(f, "{sep}{key}={value}")?,
+                _ => continue,
+            }
+            sep = '&';
+        }
+        Ok(())
+    }
+}
+
+/// Renders the reference in the URL-like syntax accepted by `inputs.<name>.url`.
+impl std::fmt::Display for FlakeRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = self.kind().unwrap_or("unknown");
+        match kind {
+            "github" | "gitlab" | "sourcehut" => {
+                write!(
+                    f,
+                    "{kind}:{}/{}",
+                    self.owner().unwrap_or_default(),
+                    self.repo().unwrap_or_default()
+                )?;
+                if let Some(rev_or_ref) = self.rev().or(self.ref_name()) {
+                    write!(f, "/{rev_or_ref}")?;
+                }
+                self.write_query(f, &["host", "dir"])
+            }
+            "git" | "hg" | "mercurial" => {
+                let scheme = if kind == "git" { "git" } else { "hg" };
+                write!(f, "{scheme}+{}", self.url().unwrap_or_default())?;
+                self.write_query(
+                    f,
+                    &["ref", "rev", "dir", "submodules", "shallow", "allRefs"],
+                )
+            }
+            "tarball" | "file" => {
+                write!(f, "{kind}+{}", self.url().unwrap_or_default())?;
+                self.write_query(f, &["dir"])
+            }
+            "path" => {
+                write!(f, "path:{}", self.path().unwrap_or_default())?;
+                self.write_query(f, &["dir"])
+            }
+            "indirect" => {
+                write!(f, "flake:{}", self.id().unwrap_or_default())?;
+                for part in [self.ref_name(), self.rev()].into_iter().flatten() {
+                    write!(f, "/{part}")?;
+                }
+                self.write_query(f, &["dir"])
+            }
+            _ => {
+                write!(f, "{kind}:")?;
+                let keys = self
+                    .0
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|&key| key != "type")
+                    .collect::<Vec<_>>();
+                self.write_query(f, &keys)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::FlakeRef;
+
+    fn display(value: serde_json::Value) -> String {
+        FlakeRef::new(value.as_object().unwrap()).to_string()
+    }
+
+    #[test]
+    fn display_urls() {
+        assert_eq!(
+            display(
+                json!({ "type": "github", "owner": "NixOS", "repo": "nixpkgs", "ref": "nixos-unstable" })
+            ),
+            "github:NixOS/nixpkgs/nixos-unstable"
+        );
+        assert_eq!(
+            display(
+                json!({ "type": "git", "url": "https://github.com/hyprwm/Hyprland", "submodules": true })
+            ),
+            "git+https://github.com/hyprwm/Hyprland?submodules=1"
+        );
+        assert_eq!(
+            display(json!({ "type": "indirect", "id": "nixpkgs" })),
+            "flake:nixpkgs"
+        );
+        assert_eq!(
+            display(json!({ "type": "path", "path": "/etc/nixos" })),
+            "path:/etc/nixos"
+        );
+    }
+}
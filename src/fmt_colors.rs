@@ -1,30 +1,228 @@
 //! If you borrow this, please give me credit.
 
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::io::{IsTerminal as _, Write as _};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Captured>> = const { RefCell::new(None) };
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+static STDOUT_COLOR: AtomicBool = AtomicBool::new(true);
+static STDERR_COLOR: AtomicBool = AtomicBool::new(true);
+
+/// How much the `elog!` and `elogln!` macros print, each message is printed
+/// if its own level is at most the level which has been set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Errors and anything else explaining a failure.
+    Quiet,
+    /// Headings and summaries.
+    #[default]
+    Normal,
+    /// Every individual change.
+    Verbose,
+    /// Intermediate state, such as reference counts.
+    Debug,
+}
+
+/// Whether to style the output of the logging macros.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only when writing to a terminal, and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Verbosity {
+    /// The level for `-q` or a number of `-v` flags.
+    pub fn from_flags(quiet: bool, verbose: usize) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Self::Quiet,
+            (false, 0) => Self::Normal,
+            (false, 1) => Self::Verbose,
+            (false, _) => Self::Debug,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(format!("expected `auto`, `always` or `never`, found `{s}`")),
+        }
+    }
+}
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+/// Whether messages of the given level are printed.
+pub fn enabled(level: Verbosity) -> bool {
+    level as u8 <= VERBOSITY.load(Ordering::Relaxed)
+}
+
+/// Decide whether standard output and standard error are styled.
+pub fn set_color(choice: ColorChoice) {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let (stdout, stderr) = match choice {
+        ColorChoice::Always => (true, true),
+        ColorChoice::Never => (false, false),
+        ColorChoice::Auto if no_color => (false, false),
+        ColorChoice::Auto => (
+            std::io::stdout().is_terminal(),
+            std::io::stderr().is_terminal(),
+        ),
+    };
+    STDOUT_COLOR.store(stdout, Ordering::Relaxed);
+    STDERR_COLOR.store(stderr, Ordering::Relaxed);
+}
+
+/// Output of the logging macros which was held back by [`capture`].
+#[derive(Clone, Debug, Default)]
+pub struct Captured {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Run `op`, collecting everything logged on this thread rather than printing it,
+/// so that logs of work done in parallel can be printed without interleaving.
+pub fn capture<T>(op: impl FnOnce() -> T) -> (T, Captured) {
+    let outer = CAPTURED.with(|cell| cell.replace(Some(Captured::default())));
+    let value = op();
+    let captured = CAPTURED
+        .with(|cell| cell.replace(outer))
+        .unwrap_or_default();
+    (value, captured)
+}
+
+impl Captured {
+    pub fn is_empty(&self) -> bool {
+        self.stdout.is_empty() && self.stderr.is_empty()
+    }
+
+    /// Print everything that was captured, each stream with a single write.
+    pub fn print(&self) {
+        let _ = std::io::stdout().lock().write_all(self.stdout.as_bytes());
+        let _ = std::io::stderr().lock().write_all(self.stderr.as_bytes());
+    }
+}
+
+#[doc(hidden)]
+pub fn write_stdout(args: std::fmt::Arguments) {
+    let text = render(args, STDOUT_COLOR.load(Ordering::Relaxed));
+    CAPTURED.with(|cell| match &mut *cell.borrow_mut() {
+        Some(captured) => captured.stdout.push_str(&text),
+        None => print!("{text}"),
+    })
+}
+
+#[doc(hidden)]
+pub fn write_stderr(args: std::fmt::Arguments) {
+    let text = render(args, STDERR_COLOR.load(Ordering::Relaxed));
+    CAPTURED.with(|cell| match &mut *cell.borrow_mut() {
+        Some(captured) => captured.stderr.push_str(&text),
+        None => eprint!("{text}"),
+    })
+}
+
+fn render(args: std::fmt::Arguments, color: bool) -> String {
+    let mut text = String::new();
+    text.write_fmt(args).unwrap();
+    if color || !text.contains('\x1b') {
+        text
+    } else {
+        strip_styles(&text)
+    }
+}
+
+/// Remove the escape sequences which style `text`.
+fn strip_styles(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+        // Control sequences end with a byte in the range `@` to `~`.
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    stripped
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! verbosity {
+    (quiet) => {
+        $crate::fmt_colors::Verbosity::Quiet
+    };
+    (verbose) => {
+        $crate::fmt_colors::Verbosity::Verbose
+    };
+    (debug) => {
+        $crate::fmt_colors::Verbosity::Debug
+    };
+}
+
 #[macro_export]
 macro_rules! log {
     ( $($args:tt)* ) => {
-        print!("{}", $crate::format_args_colored!( $($args)* ))
+        $crate::fmt_colors::write_stdout(format_args!("{}", $crate::format_args_colored!( $($args)* )))
     };
 }
 
 #[macro_export]
 macro_rules! logln {
     ( $($args:tt)* ) => {
-        println!("{}", $crate::format_args_colored!( $($args)* ))
+        $crate::fmt_colors::write_stdout(format_args!("{}\n", $crate::format_args_colored!( $($args)* )))
     };
 }
 
 #[macro_export]
 macro_rules! elog {
+    // Prefixed with `@quiet`, `@verbose` or `@debug` to print at that level.
+    ( @ $level:ident $($args:tt)* ) => {
+        if $crate::fmt_colors::enabled($crate::verbosity!($level)) {
+            $crate::fmt_colors::write_stderr(format_args!("{}", $crate::format_args_colored!( $($args)* )))
+        }
+    };
     ( $($args:tt)* ) => {
-        eprint!("{}", $crate::format_args_colored!( $($args)* ))
+        if $crate::fmt_colors::enabled($crate::fmt_colors::Verbosity::Normal) {
+            $crate::fmt_colors::write_stderr(format_args!("{}", $crate::format_args_colored!( $($args)* )))
+        }
     };
 }
 
 #[macro_export]
 macro_rules! elogln {
+    // Prefixed with `@quiet`, `@verbose` or `@debug` to print at that level.
+    ( @ $level:ident $($args:tt)* ) => {
+        if $crate::fmt_colors::enabled($crate::verbosity!($level)) {
+            $crate::fmt_colors::write_stderr(format_args!("{}\n", $crate::format_args_colored!( $($args)* )))
+        }
+    };
     ( $($args:tt)* ) => {
-        eprintln!("{}", $crate::format_args_colored!( $($args)* ))
+        if $crate::fmt_colors::enabled($crate::fmt_colors::Verbosity::Normal) {
+            $crate::fmt_colors::write_stderr(format_args!("{}\n", $crate::format_args_colored!( $($args)* )))
+        }
     };
 }
 
@@ -58,7 +256,7 @@ macro_rules! format_args_colored {
 
     // Bare parentheses with multiple items will recurse colored formatting.
     ( $(: $style:ident)* ( $($recurse:tt)+ ) $($tail:tt)* ) => {
-        format_args!( "{}{}", format_args_colored!( $($recurse)+ ) $(.$style())* , $crate::format_args_colored!( $($tail)* ) )
+        format_args!( "{}{}", $crate::format_args_colored!( $($recurse)+ ) $(.$style())* , $crate::format_args_colored!( $($tail)* ) )
     };
 
     // Parentheses prefixed with a period are treated as normal format arguments.
@@ -82,6 +280,8 @@ macro_rules! format_args_colored {
 
 #[cfg(test)]
 mod tests {
+    // The macros defined above are in textual scope, which takes precedence.
+    #[allow(unused_imports)]
     use crate::elogln;
     use owo_colors::OwoColorize;
 
@@ -125,4 +325,16 @@ mod tests {
         });
         elogln!(:green .("This is {} {} {}", "normal", "formatted", "text"))
     }
+
+    #[test]
+    fn verbosity() {
+        elogln!(@quiet :bold :red "error:", "at any verbosity");
+        elog!(@debug :dimmed "only with", :dimmed "-vv");
+    }
+
+    #[test]
+    fn strip_styles() {
+        let styled = format!("{} {}", "red".red().bold(), 10.dimmed());
+        assert_eq!(super::strip_styles(&styled), "red 10");
+    }
 }
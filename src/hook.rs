@@ -0,0 +1,80 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A git `pre-commit` hook which checks every staged `flake.lock` against
+/// the `flake.nix` beside it (if any), prunes it in place, and stages it again.
+///
+/// Staging the pruned lock stages the whole file, so the hook refuses to run
+/// on a lock with unstaged changes rather than commit them unasked.
+pub const PRE_COMMIT_HOOK: &str = r#"#!/bin/sh
+# Installed by `allfollow install-hook`.
+# Checks and prunes every staged `flake.lock`, then stages the result.
+set -eu
+
+git diff --cached --name-only --diff-filter=ACMR -- 'flake.lock' '*/flake.lock' |
+while IFS= read -r lock; do
+	if ! git diff --quiet -- "$lock"; then
+		echo "allfollow: '$lock' has unstaged changes, stage or stash them first" >&2
+		exit 1
+	fi
+	if [ -f "$(dirname "$lock")/flake.nix" ]; then
+		allfollow check "$lock"
+	fi
+	allfollow -q prune --keep-layout --in-place "$lock"
+	git add -- "$lock"
+done
+"#;
+
+/// The equivalent of [`PRE_COMMIT_HOOK`] for the `pre-commit` framework,
+/// which stages nothing itself, but fails the commit when files are modified.
+pub const PRE_COMMIT_CONFIG: &str = r#"repos:
+  - repo: local
+    hooks:
+      - id: allfollow
+        name: allfollow
+        description: Check flake.lock against flake.nix and prune it as if follows were declared everywhere
+        entry: >-
+          sh -c 'set -e; for lock; do
+          if [ -f "$(dirname "$lock")/flake.nix" ]; then allfollow check "$lock"; fi;
+          allfollow -q prune --keep-layout --in-place "$lock";
+          done' --
+        language: system
+        files: (^|/)flake\.lock$
+"#;
+
+/// Ask git where the `pre-commit` hook of the repository containing `dir` is,
+/// respecting `core.hooksPath` and linked worktrees.
+pub fn pre_commit_hook_path(dir: &Path) -> io::Result<PathBuf> {
+    let output = Command::new("git")
+        .args([
+            "rev-parse",
+            "--path-format=absolute",
+            "--git-path",
+            "hooks/pre-commit",
+        ])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+    let path = String::from_utf8_lossy(&output.stdout);
+    Ok(PathBuf::from(path.trim_end_matches(['\r', '\n'])))
+}
+
+/// Mark the installed hook as executable, so that git will run it.
+pub fn make_executable(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let mut permissions = std::fs::metadata(path)?.permissions();
+        permissions.set_mode(permissions.mode() | 0o111);
+        std::fs::set_permissions(path, permissions)?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
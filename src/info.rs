@@ -0,0 +1,218 @@
+use owo_colors::OwoColorize;
+use serde::Serialize;
+
+use crate::flake_lock::{LockFile, NodeEdge};
+use crate::format_args_colored;
+
+/// The inputs of a lock as a tree, like `nix flake metadata` shows them.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockInfo {
+    inputs: Vec<InputInfo>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputInfo {
+    name: String,
+    /// The index of the node which the input resolves to, if it exists.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    node: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    follows: Option<Vec<String>>,
+    /// The locked flake reference, including its `narHash`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rev: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modified: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nar_hash: Option<String>,
+    /// The inputs of the node, unless the input follows another.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    inputs: Vec<InputInfo>,
+}
+
+impl LockInfo {
+    pub fn new(lock: &LockFile) -> Self {
+        let mut stack = vec![lock.root_index().to_owned()];
+        Self {
+            inputs: node_inputs(lock, lock.root_index(), &mut stack),
+        }
+    }
+}
+
+/// A single input of a [`LockInfo`], for formats which are tabular.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputRow<'a> {
+    /// The input names taken from the root to reach this input.
+    path: String,
+    node: Option<&'a str>,
+    follows: Option<String>,
+    url: Option<&'a str>,
+    rev: Option<&'a str>,
+    last_modified: Option<u64>,
+    nar_hash: Option<&'a str>,
+}
+
+impl LockInfo {
+    /// Every input of the tree as a flat record, parents before their inputs.
+    pub fn rows(&self) -> Vec<InputRow<'_>> {
+        let mut rows = Vec::new();
+        push_rows(&self.inputs, "", &mut rows);
+        rows
+    }
+}
+
+fn push_rows<'a>(inputs: &'a [InputInfo], prefix: &str, rows: &mut Vec<InputRow<'a>>) {
+    for input in inputs {
+        let path = format!("{prefix}{}", input.name);
+        rows.push(InputRow {
+            path: path.clone(),
+            node: input.node.as_deref(),
+            follows: input.follows.as_ref().map(|follows| follows.join("/")),
+            url: input.url.as_deref(),
+            rev: input.rev.as_deref(),
+            last_modified: input.last_modified,
+            nar_hash: input.nar_hash.as_deref(),
+        });
+        push_rows(&input.inputs, &format!("{path}/"), rows);
+    }
+}
+
+/// The inputs of the node `index`, not descending into nodes on `stack`.
+fn node_inputs(lock: &LockFile, index: &str, stack: &mut Vec<String>) -> Vec<InputInfo> {
+    let Some(node) = lock.get_node(index) else {
+        return Vec::new();
+    };
+    let mut edges = node.iter_edges().collect::<Vec<_>>();
+    edges.sort_unstable_by_key(|&(name, _)| name);
+    edges
+        .into_iter()
+        .map(|(name, edge)| {
+            let target = lock
+                .resolve_edge(&edge)
+                .filter(|index| lock.get_node(index).is_some());
+            let mut info = InputInfo {
+                name: name.to_owned(),
+                node: target.clone(),
+                follows: edge.path().cloned(),
+                url: None,
+                rev: None,
+                last_modified: None,
+                nar_hash: None,
+                inputs: Vec::new(),
+            };
+            let (NodeEdge::Indexed(_), Some(target)) = (&*edge, target) else {
+                return info;
+            };
+            let node = lock
+                .get_node(&target)
+                .expect("a node to exist with this index");
+            if let Some(locked) = node.locked() {
+                info.rev = locked.rev().map(str::to_owned);
+                info.last_modified = locked.last_modified();
+                info.nar_hash = locked.get_str("narHash").map(str::to_owned);
+                let mut url = locked.to_string();
+                if let Some(nar_hash) = &info.nar_hash {
+                    let sep = if url.contains('?') { '&' } else { '?' };
+                    url = format!("{url}{sep}narHash={}", nar_hash.replace('=', "%3D"));
+                }
+                info.url = Some(url);
+            }
+            if !stack.contains(&target) {
+                stack.push(target.clone());
+                info.inputs = node_inputs(lock, &target, stack);
+                stack.pop();
+            }
+            info
+        })
+        .collect()
+}
+
+impl std::fmt::Display for LockInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args_colored!(:bold "Inputs:";))?;
+        write_inputs(f, &self.inputs, "")
+    }
+}
+
+fn write_inputs(
+    f: &mut std::fmt::Formatter<'_>,
+    inputs: &[InputInfo],
+    prefix: &str,
+) -> std::fmt::Result {
+    for (i, input) in inputs.iter().enumerate() {
+        let (branch, indent) = if i + 1 == inputs.len() {
+            ("└───", "    ")
+        } else {
+            ("├───", "│   ")
+        };
+        write!(f, "{prefix}{branch}")?;
+        match (&input.follows, &input.node, &input.url) {
+            (Some(follows), Some(_), _) => f.write_fmt(format_args_colored!(
+                :bold (input.name), "follows input", .("'{}'", follows.join("/"));
+            ))?,
+            (Some(follows), None, _) => f.write_fmt(format_args_colored!(
+                :bold (input.name), :red "follows non-existent input", .("'{}'", follows.join("/"));
+            ))?,
+            (None, None, _) => f.write_fmt(format_args_colored!(
+                :bold (input.name) ":", :red "does not resolve to a node";
+            ))?,
+            (None, Some(node), None) => f.write_fmt(format_args_colored!(
+                :bold (input.name) ":", :yellow "the node", .("'{node}'"), :yellow "is not locked";
+            ))?,
+            (None, Some(_), Some(url)) => {
+                f.write_fmt(format_args_colored!(:bold (input.name) ":", (url)))?;
+                if let Some(last_modified) = input.last_modified {
+                    f.write_fmt(
+                        format_args_colored!(, :dimmed .("({})", format_timestamp(last_modified))),
+                    )?;
+                }
+                writeln!(f)?;
+            }
+        }
+        write_inputs(f, &input.inputs, &format!("{prefix}{indent}"))?;
+    }
+    Ok(())
+}
+
+/// Format seconds since the Unix epoch as a date and time in UTC,
+/// such as `2024-08-06 20:06:30`.
+pub fn format_timestamp(secs: u64) -> String {
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // The days since 0000-03-01, so that leap days are the last of each year.
+    let days = days + 719_468;
+    let (era, day_of_era) = (days / 146_097, days % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_timestamp;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(format_timestamp(1_722_974_790), "2024-08-06 20:06:30");
+    }
+}
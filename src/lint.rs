@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use owo_colors::OwoColorize;
+
+use crate::elogln;
+use crate::flake_lock::{LockFile, Node, NodeEdge};
+
+/// A structural problem with a lock, which Nix may reject.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    /// The node named by `root` does not exist.
+    MissingRoot { index: String },
+    /// An edge references a node index which does not exist.
+    DanglingIndex {
+        node: String,
+        input: String,
+        index: String,
+    },
+    /// A follows edge names a path which does not lead to a node.
+    UnresolvedFollows {
+        node: String,
+        input: String,
+        follows: Vec<String>,
+    },
+    /// The root node has `locked` and `original` attributes.
+    LockedRoot { index: String },
+    /// A node other than the root lacks `locked` and `original` attributes.
+    UnlockedNode { index: String },
+    /// An edge leads back to a node which it is reachable from.
+    Cycle {
+        node: String,
+        input: String,
+        cycle: Vec<String>,
+    },
+    /// A node is not reachable from the root.
+    Orphan { index: String },
+    /// The `locked` attributes of a node do not include a `narHash`.
+    MissingNarHash { index: String },
+}
+
+impl Problem {
+    /// Whether Nix would fail to use the lock, as opposed to the lock
+    /// merely containing something unexpected.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Self::Orphan { .. } | Self::MissingNarHash { .. })
+    }
+
+    /// The location of the problem in the lock, in the syntax of `jq`.
+    pub fn json_path(&self) -> String {
+        match self {
+            Self::MissingRoot { .. } => json_path(&["root"]),
+            Self::DanglingIndex { node, input, .. }
+            | Self::UnresolvedFollows { node, input, .. }
+            | Self::Cycle { node, input, .. } => json_path(&["nodes", node, "inputs", input]),
+            Self::LockedRoot { index } | Self::UnlockedNode { index } | Self::Orphan { index } => {
+                json_path(&["nodes", index])
+            }
+            Self::MissingNarHash { index } => json_path(&["nodes", index, "locked"]),
+        }
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingRoot { index } => write!(f, "the root node '{index}' does not exist"),
+            Self::DanglingIndex { index, .. } => {
+                write!(f, "references the node '{index}' which does not exist")
+            }
+            Self::UnresolvedFollows { follows, .. } => {
+                write!(
+                    f,
+                    "follows '{}' which does not resolve to a node",
+                    follows.join("/")
+                )
+            }
+            Self::LockedRoot { .. } => write!(f, "the root node should not be locked"),
+            Self::UnlockedNode { .. } => write!(f, "the node is not locked"),
+            Self::Cycle { cycle, .. } => write!(f, "forms a cycle '{}'", cycle.join("' -> '")),
+            Self::Orphan { .. } => write!(f, "the node is not reachable from the root"),
+            Self::MissingNarHash { .. } => write!(f, "the node is locked without a 'narHash'"),
+        }
+    }
+}
+
+/// Find every structural problem with the lock, ordered by location.
+pub fn lint_lock(lock: &LockFile) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let root_index = lock.root_index();
+    let mut indices = lock.node_indices().collect::<Vec<_>>();
+    indices.sort_unstable();
+
+    match lock.root() {
+        None => problems.push(Problem::MissingRoot {
+            index: root_index.to_owned(),
+        }),
+        Some(root) if matches!(*root, Node::Locked(_)) => problems.push(Problem::LockedRoot {
+            index: root_index.to_owned(),
+        }),
+        Some(_) => {}
+    }
+
+    // Every edge which resolves, by the index of the node owning it.
+    let mut graph = BTreeMap::<&str, Vec<(String, String)>>::new();
+    for &index in &indices {
+        let node = lock
+            .get_node(index)
+            .expect("a node to exist with this index");
+        if index != root_index {
+            match node.locked() {
+                None => problems.push(Problem::UnlockedNode {
+                    index: index.to_owned(),
+                }),
+                Some(locked) if locked.get_str("narHash").is_none() => {
+                    problems.push(Problem::MissingNarHash {
+                        index: index.to_owned(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        let mut edges = node.iter_edges().collect::<Vec<_>>();
+        edges.sort_unstable_by_key(|&(name, _)| name);
+        for (name, edge) in edges {
+            match resolve_edge(lock, &edge) {
+                Some(target) => graph
+                    .entry(index)
+                    .or_default()
+                    .push((name.to_owned(), target)),
+                None => problems.push(match &*edge {
+                    NodeEdge::Indexed(target) => Problem::DanglingIndex {
+                        node: index.to_owned(),
+                        input: name.to_owned(),
+                        index: target.clone(),
+                    },
+                    NodeEdge::Follows(follows) => Problem::UnresolvedFollows {
+                        node: index.to_owned(),
+                        input: name.to_owned(),
+                        follows: follows.clone(),
+                    },
+                }),
+            }
+        }
+    }
+
+    let mut visited = BTreeSet::new();
+    let mut stack = Vec::new();
+    if lock.root().is_some() {
+        visit(root_index, &graph, &mut visited, &mut stack, &mut problems);
+    }
+    for &index in &indices {
+        if !visited.contains(index) {
+            problems.push(Problem::Orphan {
+                index: index.to_owned(),
+            });
+        }
+    }
+    // Orphans may have cycles of their own.
+    for &index in &indices {
+        if !visited.contains(index) {
+            visit(index, &graph, &mut visited, &mut stack, &mut problems);
+        }
+    }
+
+    problems.sort_by_cached_key(Problem::json_path);
+    problems
+}
+
+/// Depth-first search, reporting every edge which leads to a node on the stack.
+fn visit<'a>(
+    index: &'a str,
+    graph: &'a BTreeMap<&str, Vec<(String, String)>>,
+    visited: &mut BTreeSet<&'a str>,
+    stack: &mut Vec<&'a str>,
+    problems: &mut Vec<Problem>,
+) {
+    visited.insert(index);
+    stack.push(index);
+    for (name, target) in graph.get(index).into_iter().flatten() {
+        if let Some(start) = stack.iter().position(|&i| i == target) {
+            let mut cycle = stack[start..]
+                .iter()
+                .map(|&i| i.to_owned())
+                .collect::<Vec<_>>();
+            cycle.push(target.clone());
+            problems.push(Problem::Cycle {
+                node: index.to_owned(),
+                input: name.clone(),
+                cycle,
+            });
+        } else if !visited.contains(target.as_str()) {
+            visit(target, graph, visited, stack, problems);
+        }
+    }
+    stack.pop();
+}
+
+/// Like [`LockFile::resolve_edge`], but also requiring the node to exist.
+pub fn resolve_edge(lock: &LockFile, edge: &NodeEdge) -> Option<String> {
+    lock.resolve_edge(edge)
+        .filter(|index| lock.get_node(index).is_some())
+}
+
+/// Format a path of object keys as understood by `jq`.
+fn json_path(keys: &[&str]) -> String {
+    let mut path = String::new();
+    for key in keys {
+        let is_ident = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if is_ident {
+            path.push('.');
+            path.push_str(key);
+        } else {
+            path.push('[');
+            path.push_str(&serde_json::to_string(key).expect("a string to serialize"));
+            path.push(']');
+        }
+    }
+    path
+}
+
+pub fn elog_problems(problems: &[Problem]) {
+    for problem in problems {
+        if problem.is_error() {
+            elogln!(@quiet :bold :bright_red "error:", :yellow (problem.json_path()), (problem));
+        } else {
+            elogln!(:bold :bright_yellow "warning:", :yellow (problem.json_path()), (problem));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint_lock, Problem};
+    use crate::flake_lock::LockFile;
+
+    #[test]
+    fn problems() {
+        let lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "a": {
+                        "inputs": { "b": "b", "gone": "gone", "loop": ["a", "loop"] },
+                        "locked": { "type": "github", "owner": "o", "repo": "a", "narHash": "sha256-" },
+                        "original": { "type": "github", "owner": "o", "repo": "a" }
+                    },
+                    "b": {
+                        "inputs": { "a": "a" },
+                        "locked": { "type": "github", "owner": "o", "repo": "b" },
+                        "original": { "type": "github", "owner": "o", "repo": "b" }
+                    },
+                    "c-d": { "inputs": {} },
+                    "root": { "inputs": { "a": "a" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let problems = lint_lock(&lock);
+        let paths = problems
+            .iter()
+            .map(|problem| (problem.json_path(), problem.is_error()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                (".nodes.a.inputs.gone".to_owned(), true),
+                (".nodes.a.inputs.loop".to_owned(), true),
+                (".nodes.b.inputs.a".to_owned(), true),
+                (".nodes.b.locked".to_owned(), false),
+                (r#".nodes["c-d"]"#.to_owned(), true),
+                (r#".nodes["c-d"]"#.to_owned(), false),
+            ]
+        );
+        assert_eq!(
+            problems[2],
+            Problem::Cycle {
+                node: "b".to_owned(),
+                input: "a".to_owned(),
+                cycle: ["a", "b", "a"].map(str::to_owned).to_vec(),
+            }
+        );
+    }
+}
@@ -1,19 +1,66 @@
+mod age;
+mod batch;
+mod check;
 mod cli_args;
+mod config;
+mod count;
+mod edit;
 mod flake_lock;
+mod flake_nix;
+mod flake_ref;
 mod fmt_colors;
+mod hook;
+mod info;
+mod lint;
+mod merge;
+mod nar;
+mod nar_hash;
+mod nix_emit;
+mod output_format;
+mod pin;
+mod policy;
+mod promote;
+mod repair;
+mod report;
+mod review;
+mod sbom;
+mod stats;
+mod watch;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::io::{self, IsTerminal as _, Read, Write as _};
 use std::iter::repeat;
+use std::path::PathBuf;
 
+use age::{AgeReport, Period};
+use batch::Documents;
 use bpaf::Bpaf;
+use check::{compare_flake_inputs, describe_edge, elog_mismatches};
 use cli_args::{Input, Output};
+use config::Config;
+use count::{NodeTable, SortKey};
 use flake_lock::{
     LockFile, Node, NodeEdge, NodeEdgeRef as _, MAX_SUPPORTED_LOCK_VERSION,
     MIN_SUPPORTED_LOCK_VERSION,
 };
+use flake_nix::InputDecls;
+use fmt_colors::{ColorChoice, Verbosity};
+use indexmap::IndexMap;
+use lint::elog_problems;
+use merge::{Conflict, Prefer};
+use nar_hash::{HashEncoding, NarHash};
+use nix_emit::FollowsDeclarations;
+use output_format::{JsonLayout, OutputFormat};
 use owo_colors::OwoColorize;
+use policy::Policy;
+use promote::{elog_promoted_inputs, promote_shared_inputs, PromotedInput};
+use repair::RepairedEdge;
+use report::PruneReport;
+use review::Review;
+use sbom::{Sbom, SbomFormat};
 use serde::Serialize;
-use serde_json::Serializer;
+use stats::LockStats;
 
 static EXPECT_ROOT_EXIST: &str = "the root node to exist";
 
@@ -24,39 +71,520 @@ static EXPECT_ROOT_EXIST: &str = "the root node to exist";
 /// `inputs.*.inputs.*.follows = "*";` in your `flake.nix` with automation.
 #[derive(Debug, Clone, Bpaf)]
 #[bpaf(options, generate(parse_command_env_args))]
+struct Args {
+    //
+    #[bpaf(external(log_options))]
+    log_opts: LogOptions,
+    //
+    #[bpaf(external(command))]
+    command: Command,
+}
+
+/// Options for logging, which must precede the command:
+#[derive(Debug, Clone, Bpaf)]
+struct LogOptions {
+    /// Only print errors
+    #[bpaf(short('q'), long)]
+    quiet: bool,
+    /// Print every change, and reference counts if given twice
+    #[bpaf(short('v'), long("verbose"), req_flag(()), count)]
+    verbose: usize,
+    /// Whether to style logs, one of `auto`, `always` or `never`
+    #[bpaf(long, argument("WHEN"), fallback(ColorChoice::Auto))]
+    color: ColorChoice,
+}
+
+#[derive(Debug, Clone, Bpaf)]
 enum Command {
     #[bpaf(command("prune"))]
     Prune {
         /// Do not imitate `inputs.*.follows`, reference node indices instead
         #[bpaf(long, long("indexed"))]
         no_follows: bool,
+        /// Add root inputs for sources shared by several inputs, and follow those
+        #[bpaf(long)]
+        promote: bool,
+        /// Ignore `allfollow.toml`, so that no inputs are excluded
+        #[bpaf(long)]
+        no_config: bool,
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
+        /// Write the lock with the same indentation as it was read, instead of `--pretty`
+        #[bpaf(short('k'), long)]
+        keep_layout: bool,
+        /// Also write a report of every change made to `REPORT`
+        #[bpaf(long, argument("REPORT"))]
+        report: Option<PathBuf>,
+        /// Write the report as `json` (default), `csv`, `toml` or `yaml`
+        #[bpaf(long, argument("FORMAT"), fallback(OutputFormat::Json))]
+        report_format: OutputFormat,
+        //
+        #[bpaf(external(output_options))]
+        output_opts: OutputOptions,
+        /// Search directories for every `flake.lock` not ignored by git
+        #[bpaf(short('r'), long)]
+        recursive: bool,
+        /// The paths of `flake.lock` files or the directories containing them,
+        /// or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("INPUT"), many)]
+        lock_files: Vec<Input>,
+    },
+    #[bpaf(command("count"))]
+    Count {
+        /// Show the data as JSON, the same as `--format json`
+        #[bpaf(short('j'), long)]
+        json: bool,
+        /// Show the data as `json`, `csv`, `toml` or `yaml` rather than a table
+        #[bpaf(long, argument("FORMAT"))]
+        format: Option<OutputFormat>,
         /// Do not minify the output JSON
         #[bpaf(short('p'), long)]
         pretty: bool,
+        /// Order the table by `count`, `name` (default) or `source`
+        #[bpaf(long, argument("KEY"), fallback(SortKey::Name))]
+        sort: SortKey,
+        /// Only show nodes which share their source with another node
+        #[bpaf(long)]
+        only_duplicates: bool,
+        /// Only show nodes which are not reachable from the root
+        #[bpaf(long)]
+        only_orphans: bool,
         //
         #[bpaf(external(output_options))]
         output_opts: OutputOptions,
+        /// Search directories for every `flake.lock` not ignored by git
+        #[bpaf(short('r'), long)]
+        recursive: bool,
+        /// The paths of `flake.lock` files or the directories containing them,
+        /// or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("INPUT"), many)]
+        lock_files: Vec<Input>,
+    },
+    /// Summarize the shape of the lock, and which inputs duplicate others
+    #[bpaf(command("stats"))]
+    Stats {
+        /// Show the data as JSON, the same as `--format json`
+        #[bpaf(short('j'), long)]
+        json: bool,
+        /// Show the data as `json`, `csv`, `toml` or `yaml` rather than tables
+        #[bpaf(long, argument("FORMAT"))]
+        format: Option<OutputFormat>,
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
+        /// List the `N` inputs which bring in the most duplicates, default 5
+        #[bpaf(long, argument("N"), fallback(5))]
+        top: usize,
+        /// Search directories for every `flake.lock` not ignored by git
+        #[bpaf(short('r'), long)]
+        recursive: bool,
+        /// The paths of `flake.lock` files or the directories containing them,
+        /// or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("INPUT"), many)]
+        lock_files: Vec<Input>,
+    },
+    /// Prune the lock in place every time that it changes
+    #[bpaf(command("watch"))]
+    Watch {
+        /// Do not imitate `inputs.*.follows`, reference node indices instead
+        #[bpaf(long, long("indexed"))]
+        no_follows: bool,
+        /// Add root inputs for sources shared by several inputs, and follow those
+        #[bpaf(long)]
+        promote: bool,
+        /// Ignore `allfollow.toml`, so that no inputs are excluded
+        #[bpaf(long)]
+        no_config: bool,
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
+        /// Write the lock with the same indentation as it was read, instead of `--pretty`
+        #[bpaf(short('k'), long)]
+        keep_layout: bool,
+        //
+        #[bpaf(external(backup_options))]
+        backup_opts: BackupOptions,
+        /// The path of `flake.lock` to watch.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("INPUT"), fallback(PathBuf::from("./flake.lock")))]
+        lock_file: PathBuf,
+    },
+    /// Compare the inputs declared in `flake.nix` with those in the lock
+    #[bpaf(command("check"))]
+    Check {
+        /// The path of `flake.nix` to read.
+        /// If unspecified, defaults to the one beside each `INPUT`.
+        #[bpaf(long, argument("FLAKE_NIX"))]
+        flake_nix: Option<PathBuf>,
+        /// Search directories for every `flake.lock` not ignored by git
+        #[bpaf(short('r'), long)]
+        recursive: bool,
+        /// The paths of `flake.lock` files or the directories containing them,
+        /// or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("INPUT"), many)]
+        lock_files: Vec<Input>,
+    },
+    /// Show the inputs of the lock like `nix flake metadata`, without needing Nix
+    #[bpaf(command("info"))]
+    Info {
+        /// Show the inputs as JSON, the same as `--format json`
+        #[bpaf(short('j'), long)]
+        json: bool,
+        /// Show the inputs as `json`, `csv`, `toml` or `yaml` rather than a tree
+        #[bpaf(long, argument("FORMAT"))]
+        format: Option<OutputFormat>,
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
         /// The path of `flake.lock` to read, or `-` to read from standard input.
         /// If unspecified, defaults to the current directory.
         #[bpaf(positional("INPUT"), fallback(Input::from("./flake.lock")))]
         lock_file: Input,
     },
-    #[bpaf(command("count"))]
-    Count {
-        /// Show the data as JSON.
+    /// Write a software bill of materials listing every locked input
+    #[bpaf(command("sbom"))]
+    Sbom {
+        /// The standard to follow, `cyclonedx` (default) or `spdx`
+        #[bpaf(long, argument("FORMAT"), fallback(SbomFormat::CycloneDx))]
+        format: SbomFormat,
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
+        /// Overwrite the output file if it exists
+        #[bpaf(short('f'), long, long("force"))]
+        overwrite: bool,
+        /// Path of the file to write, set to `-` for stdout (default)
+        #[bpaf(short('o'), long, argument("OUTPUT"), fallback(Output::Stdout))]
+        output: Output,
+        /// The path of `flake.lock` to read, or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("INPUT"), fallback(Input::from("./flake.lock")))]
+        lock_file: Input,
+    },
+    /// List locked inputs by age, flagging those which are stale
+    #[bpaf(command("age"))]
+    Age {
+        /// Flag inputs last modified longer ago than `AGE`, such as `12w`, default `90d`
+        #[bpaf(long, argument("AGE"), fallback(Period::days(90)))]
+        max_age: Period,
+        /// Flag copies of a source older than its newest copy by more than `AGE`, default `90d`
+        #[bpaf(long, argument("AGE"), fallback(Period::days(90)))]
+        max_spread: Period,
+        /// Exit with an error if any input is flagged
+        #[bpaf(long)]
+        fail_on_stale: bool,
+        /// Show the data as JSON, the same as `--format json`
         #[bpaf(short('j'), long)]
         json: bool,
+        /// Show the data as `json`, `csv`, `toml` or `yaml` rather than a table
+        #[bpaf(long, argument("FORMAT"))]
+        format: Option<OutputFormat>,
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
+        /// Search directories for every `flake.lock` not ignored by git
+        #[bpaf(short('r'), long)]
+        recursive: bool,
+        /// The paths of `flake.lock` files or the directories containing them,
+        /// or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("INPUT"), many)]
+        lock_files: Vec<Input>,
+    },
+    /// Check that every input comes from a source which the rules allow
+    #[bpaf(command("policy"))]
+    Policy {
+        /// Only allow inputs of `OWNER`, or fetched from URLs beneath `/OWNER/`, may be repeated
+        #[bpaf(long("allow-owner"), argument("OWNER"), many)]
+        allowed_owners: Vec<String>,
+        /// Forbid inputs from local paths
+        #[bpaf(long)]
+        forbid_path: bool,
+        /// Forbid inputs locked without a `narHash`
+        #[bpaf(long)]
+        require_nar_hash: bool,
+        /// Forbid inputs looked up in the flake registry, such as `nixpkgs`
+        #[bpaf(long)]
+        forbid_indirect: bool,
+        /// Forbid `tarball` and `file` inputs fetched without `https`
+        #[bpaf(long)]
+        https_only: bool,
+        /// Search directories for every `flake.lock` not ignored by git
+        #[bpaf(short('r'), long)]
+        recursive: bool,
+        /// The paths of `flake.lock` files or the directories containing them,
+        /// or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("INPUT"), many)]
+        lock_files: Vec<Input>,
+    },
+    /// Check the lock for dangling references, cycles and other structural problems
+    #[bpaf(command("lint"))]
+    Lint {
+        /// Search directories for every `flake.lock` not ignored by git
+        #[bpaf(short('r'), long)]
+        recursive: bool,
+        /// The paths of `flake.lock` files or the directories containing them,
+        /// or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("INPUT"), many)]
+        lock_files: Vec<Input>,
+    },
+    /// Redirect edges which do not resolve where the intent is clear,
+    /// and remove nodes which are unreachable
+    #[bpaf(command("repair"))]
+    Repair {
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
+        //
+        #[bpaf(external(output_options))]
+        output_opts: OutputOptions,
+        /// Search directories for every `flake.lock` not ignored by git
+        #[bpaf(short('r'), long)]
+        recursive: bool,
+        /// The paths of `flake.lock` files or the directories containing them,
+        /// or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("INPUT"), many)]
+        lock_files: Vec<Input>,
+    },
+    /// Merge two locks which diverged from a common ancestor, as a git merge driver
+    ///
+    /// To use it, add `flake.lock merge=allfollow` to `.gitattributes` and run
+    /// `git config merge.allfollow.driver "allfollow merge %O %A %B"`.
+    #[bpaf(command("merge"))]
+    Merge {
+        /// When both sides changed an input, take the `newer` (default), `ours` or `theirs`
+        #[bpaf(long, argument("SIDE"), fallback(Prefer::Newer))]
+        prefer: Prefer,
+        /// Do not imitate `inputs.*.follows`, reference node indices instead
+        #[bpaf(long, long("indexed"))]
+        no_follows: bool,
+        /// Ignore `allfollow.toml`, so that no inputs are excluded
+        #[bpaf(long)]
+        no_config: bool,
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
+        /// Overwrite the output file if it exists
+        #[bpaf(short('f'), long, long("force"))]
+        overwrite: bool,
+        /// Path of the file to write, set to `-` for stdout.
+        /// If unspecified, `OURS` is overwritten, as git expects.
+        #[bpaf(short('o'), long, argument("OUTPUT"))]
+        output: Option<Output>,
+        /// The lock which both sides changed, `%O` for git
+        #[bpaf(positional("BASE"))]
+        base: PathBuf,
+        /// The lock of the current branch, `%A` for git
+        #[bpaf(positional("OURS"))]
+        ours: PathBuf,
+        /// The lock of the branch being merged, `%B` for git
+        #[bpaf(positional("THEIRS"))]
+        theirs: PathBuf,
+    },
+    /// Make an input follow another, such as `hyprland/nixpkgs` following `nixpkgs`
+    #[bpaf(command("set-follows"))]
+    SetFollows {
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
+        /// Write the lock with the same indentation as it was read, instead of `--pretty`
+        #[bpaf(short('k'), long)]
+        keep_layout: bool,
+        //
+        #[bpaf(external(output_options))]
+        output_opts: OutputOptions,
+        /// The path of the input to change, such as `hyprland/nixpkgs`
+        #[bpaf(positional("INPUT"))]
+        input: String,
+        /// The path of the input to follow, such as `nixpkgs`
+        #[bpaf(positional("TARGET"))]
+        target: String,
+        /// The path of `flake.lock` to edit, or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("LOCK"), fallback(Input::from("./flake.lock")))]
+        lock_file: Input,
+    },
+    /// Give an input which follows another its own copy of the node it follows
+    #[bpaf(command("unfollow"))]
+    Unfollow {
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
+        /// Write the lock with the same indentation as it was read, instead of `--pretty`
+        #[bpaf(short('k'), long)]
+        keep_layout: bool,
+        //
+        #[bpaf(external(output_options))]
+        output_opts: OutputOptions,
+        /// The path of the input to change, such as `hyprland/nixpkgs`
+        #[bpaf(positional("INPUT"))]
+        input: String,
+        /// The path of `flake.lock` to edit, or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("LOCK"), fallback(Input::from("./flake.lock")))]
+        lock_file: Input,
+    },
+    /// Remove an input, and every node which is no longer used
+    #[bpaf(command("remove-input"))]
+    RemoveInput {
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
+        /// Write the lock with the same indentation as it was read, instead of `--pretty`
+        #[bpaf(short('k'), long)]
+        keep_layout: bool,
+        //
+        #[bpaf(external(output_options))]
+        output_opts: OutputOptions,
+        /// The path of the input to remove, such as `nixpkgs` or `hyprland/nixpkgs`
+        #[bpaf(positional("INPUT"))]
+        input: String,
+        /// The path of `flake.lock` to edit, or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("LOCK"), fallback(Input::from("./flake.lock")))]
+        lock_file: Input,
+    },
+    /// Copy an input, and every node that it depends on, from another lock
+    #[bpaf(command("graft"))]
+    Graft {
         /// Do not minify the output JSON
         #[bpaf(short('p'), long)]
         pretty: bool,
+        /// Write the lock with the same indentation as it was read, instead of `--pretty`
+        #[bpaf(short('k'), long)]
+        keep_layout: bool,
         //
         #[bpaf(external(output_options))]
         output_opts: OutputOptions,
+        /// The path of the lock to copy from
+        #[bpaf(long, argument("OTHER"))]
+        from: PathBuf,
+        /// The path of the input to copy, such as `nixpkgs` or `hyprland/nixpkgs`
+        #[bpaf(long, argument("INPUT"))]
+        input: String,
+        /// The path of the input to replace with the copy, if it is not `INPUT`
+        #[bpaf(long, argument("TARGET"))]
+        to: Option<String>,
+        /// The path of `flake.lock` to edit, or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("LOCK"), fallback(Input::from("./flake.lock")))]
+        lock_file: Input,
+    },
+    /// Lock every copy of a source to a given revision
+    #[bpaf(command("pin"))]
+    Pin {
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
+        /// Write the lock with the same indentation as it was read, instead of `--pretty`
+        #[bpaf(short('k'), long)]
+        keep_layout: bool,
+        //
+        #[bpaf(external(output_options))]
+        output_opts: OutputOptions,
+        /// The commit hash to lock the source to
+        #[bpaf(long, argument("REV"))]
+        rev: String,
+        /// The NAR hash of the source at `REV`, as SRI such as `sha256-...`, base-16 or base-32
+        #[bpaf(long, argument("HASH"))]
+        nar_hash: String,
+        /// The commit time of `REV` in seconds since the Unix epoch.
+        /// If unspecified, it is removed for Nix to find.
+        #[bpaf(long, argument("SECONDS"))]
+        last_modified: Option<u64>,
+        /// Keep only one node of the source, and redirect every input to it
+        #[bpaf(long)]
+        redirect: bool,
+        /// The source as originally declared, such as `github:NixOS/nixpkgs`,
+        /// matching any ref which it does not mention
+        #[bpaf(positional("SOURCE"))]
+        source: String,
+        /// The path of `flake.lock` to edit, or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("LOCK"), fallback(Input::from("./flake.lock")))]
+        lock_file: Input,
+    },
+    /// Hash a local path or NAR file as Nix would, without needing Nix,
+    /// and check it against the `narHash` of an input
+    #[bpaf(command("verify"))]
+    Verify {
+        /// Check the hash against the input at the path `INPUT`, such as `hyprland/nixpkgs`
+        #[bpaf(short('i'), long, argument("INPUT"))]
+        input: Option<String>,
+        /// Print the hash as `sri` (default), `base16` or `base32`
+        #[bpaf(long, argument("ENCODING"), fallback(HashEncoding::Sri))]
+        encoding: HashEncoding,
+        /// The file or directory to hash, or a NAR file which is hashed as it is
+        #[bpaf(positional("PATH"))]
+        path: PathBuf,
+        /// The path of `flake.lock` to read for `--input`, or `-` to read from standard input.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("LOCK"), fallback(Input::from("./flake.lock")))]
+        lock_file: Input,
+    },
+    /// Install a git pre-commit hook which prunes every staged lock
+    #[bpaf(command("install-hook"))]
+    InstallHook {
+        /// Print a configuration for the `pre-commit` framework instead
+        #[bpaf(long)]
+        pre_commit_config: bool,
+        /// Overwrite the hook if it exists
+        #[bpaf(short('f'), long, long("force"))]
+        overwrite: bool,
+        /// A directory within the git repository.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("REPO"), fallback(PathBuf::from(".")))]
+        repo: PathBuf,
+    },
+    /// Print the `flake.nix` follows declarations equivalent to `prune`
+    #[bpaf(command("emit-nix"))]
+    EmitNix {
+        /// Also declare root inputs for sources shared by several inputs
+        #[bpaf(long)]
+        promote: bool,
+        /// Ignore `allfollow.toml`, so that no inputs are excluded
+        #[bpaf(long)]
+        no_config: bool,
+        /// Overwrite the output file if it exists
+        #[bpaf(short('f'), long, long("force"))]
+        overwrite: bool,
+        /// Path of the file to write, set to `-` for stdout (default)
+        #[bpaf(short('o'), long, argument("OUTPUT"), fallback(Output::Stdout))]
+        output: Output,
         /// The path of `flake.lock` to read, or `-` to read from standard input.
         /// If unspecified, defaults to the current directory.
         #[bpaf(positional("INPUT"), fallback(Input::from("./flake.lock")))]
         lock_file: Input,
     },
+    /// Accept or reject each redirection `prune` would make in a terminal UI,
+    /// then save the lock and the rejected inputs to `allfollow.toml`
+    #[bpaf(command("review"))]
+    Review {
+        /// Do not imitate `inputs.*.follows`, reference node indices instead
+        #[bpaf(long, long("indexed"))]
+        no_follows: bool,
+        /// Do not minify the output JSON
+        #[bpaf(short('p'), long)]
+        pretty: bool,
+        /// Write the lock with the same indentation as it was read, instead of `--pretty`
+        #[bpaf(short('k'), long)]
+        keep_layout: bool,
+        //
+        #[bpaf(external(backup_options))]
+        backup_opts: BackupOptions,
+        /// The path of `flake.lock` to review, which is overwritten when saving.
+        /// If unspecified, defaults to the current directory.
+        #[bpaf(positional("LOCK"), fallback(Input::from("./flake.lock")))]
+        lock_file: Input,
+    },
 }
 
 /// Generic options for output handling:
@@ -71,141 +599,1121 @@ struct OutputOptions {
     /// Path of the file to write, set to `-` for stdout (default)
     #[bpaf(short('o'), long, argument("OUTPUT"), fallback(Output::Stdout))]
     output: Output,
+    //
+    #[bpaf(external(backup_options))]
+    backup_opts: BackupOptions,
 }
 
-impl Command {
-    fn from_env() -> Self {
-        let mut args = parse_command_env_args().run();
-        #[allow(clippy::single_match)]
-        match &mut args {
-            Command::Prune {
-                lock_file,
-                output_opts,
-                ..
-            }
-            | Command::Count {
-                lock_file,
-                output_opts,
-                ..
-            } => {
-                if output_opts.in_place {
-                    output_opts.output = Output::from(lock_file.clone());
-                    output_opts.overwrite = true;
-                }
-            }
-        };
-        args
+/// Options for keeping the file which is overwritten:
+#[derive(Debug, Clone, Bpaf)]
+struct BackupOptions {
+    /// Keep a copy of the overwritten file as `OUTPUT.bak`
+    #[bpaf(short('b'), long)]
+    backup: bool,
+    /// Keep a copy of the overwritten file with `SUFFIX` appended to its name
+    #[bpaf(long, argument("SUFFIX"))]
+    backup_suffix: Option<String>,
+}
+
+impl BackupOptions {
+    fn suffix(&self) -> Option<&str> {
+        match (&self.backup_suffix, self.backup) {
+            (Some(suffix), _) => Some(suffix),
+            (None, true) => Some(".bak"),
+            (None, false) => None,
+        }
+    }
+}
+
+impl OutputOptions {
+    /// Where to write the result for `lock_file`, and whether to overwrite it.
+    fn resolve(&self, lock_file: &Input) -> (Output, bool) {
+        if self.in_place {
+            (Output::from(lock_file.clone()), true)
+        } else {
+            (self.output.clone(), self.overwrite)
+        }
     }
 }
 
 fn main() {
-    match Command::from_env() {
+    let Args { log_opts, command } = parse_command_env_args().run();
+    fmt_colors::set_verbosity(Verbosity::from_flags(log_opts.quiet, log_opts.verbose));
+    fmt_colors::set_color(log_opts.color);
+
+    match command {
         Command::Prune {
             no_follows,
-            lock_file,
+            promote,
+            no_config,
             pretty,
-            output_opts:
-                OutputOptions {
-                    in_place: _,
-                    overwrite,
-                    output,
-                },
+            keep_layout,
+            report,
+            report_format,
+            output_opts,
+            recursive,
+            lock_files,
         } => {
-            let mut lock = read_flake_lock(lock_file);
+            let lock_files = resolve_lock_files(lock_files, recursive);
+            if lock_files.len() > 1 && !output_opts.in_place {
+                exit_with_error("Pruning several lock files requires `--in-place`")
+            }
+            if lock_files.len() > 1 && report.is_some() {
+                exit_with_error("Pruning several lock files cannot write to a single `--report`")
+            }
+            let succeeded = batch::for_each_lock(&lock_files, |lock_file| {
+                let (mut lock, layout) = read_flake_lock_and_layout(lock_file)?;
+                let exclude = Config::exclusions_for(lock_file, no_config)?;
+                let layout = if keep_layout {
+                    layout
+                } else {
+                    JsonLayout::from_pretty(pretty)
+                };
 
-            let node_hits = FlakeNodeVisits::count_from_index(&lock, lock.root_index());
-            eprintln!();
-            elogln!(:bold :bright_magenta "Flake input nodes' reference counts:"; &node_hits);
+                let node_hits = FlakeNodeVisits::count_from_index(&lock, lock.root_index());
+                elogln!(@debug);
+                elogln!(@debug :bold :bright_magenta "Flake input nodes' reference counts:"; &node_hits);
+                let references_before = node_hits.to_sorted();
 
-            substitute_flake_inputs_with_follows(&lock, no_follows);
-            eprintln!();
-            prune_orphan_nodes(&mut lock);
+                let outcome = prune_lock(&mut lock, no_follows, promote, &exclude);
 
-            eprintln!();
-            let node_hits = FlakeNodeVisits::count_from_index(&lock, lock.root_index());
-            elog!(
-                :bold (:bright_magenta "Flake input nodes' reference counts", :bright_green "after successful pruning" :bright_magenta ":");
-                &node_hits
-            );
-            eprintln!();
+                elogln!(@debug);
+                let node_hits = FlakeNodeVisits::count_from_index(&lock, lock.root_index());
+                elog!(@debug
+                    :bold (:bright_magenta "Flake input nodes' reference counts", :bright_green "after successful pruning" :bright_magenta ":");
+                    &node_hits
+                );
+                elogln!();
+
+                if !outcome.promoted.is_empty() {
+                    elog_promoted_inputs(&outcome.promoted);
+                    elogln!();
+                }
 
-            serialize_to_json_output(&lock, output, overwrite, pretty)
+                if let Some(report) = &report {
+                    let report_json =
+                        PruneReport::new(&outcome, references_before, node_hits.to_sorted());
+                    let report = Output::from(report);
+                    if report_format == OutputFormat::Csv {
+                        serialize_to_output(
+                            report_json.changes(),
+                            report_format,
+                            report,
+                            true,
+                            None,
+                            pretty,
+                        )?;
+                    } else {
+                        serialize_to_output(
+                            report_json,
+                            report_format,
+                            report,
+                            true,
+                            None,
+                            pretty,
+                        )?;
+                    }
+                }
+
+                let (output, overwrite) = output_opts.resolve(lock_file);
+                let json = serialize_to_json_string(&lock, &layout)?;
+                write_display_output(json, output, overwrite, output_opts.backup_opts.suffix())?;
+                Ok(format!("pruned {} nodes", outcome.removed.len()))
+            });
+            exit_on_failure(succeeded)
         }
         Command::Count {
             json,
+            format,
+            pretty,
+            sort,
+            only_duplicates,
+            only_orphans,
+            output_opts,
+            recursive,
+            lock_files,
+        } => {
+            let lock_files = resolve_lock_files(lock_files, recursive);
+            if lock_files.len() > 1
+                && !output_opts.in_place
+                && matches!(output_opts.output, Output::File(_))
+            {
+                exit_with_error("Counting several lock files cannot write to a single `--output`")
+            }
+            let format = format.or(json.then_some(OutputFormat::Json));
+            let documents = Documents::new(&lock_files, format.unwrap_or_default(), pretty);
+            let succeeded = batch::for_each_lock(&lock_files, |lock_file| {
+                let lock = read_flake_lock(lock_file)?;
+                let node_hits = FlakeNodeVisits::count_from_index(&lock, lock.root_index());
+                let mut table = NodeTable::new(&lock, &node_hits);
+                table.sort_by(sort);
+                if only_duplicates {
+                    table.retain_duplicates();
+                }
+                if only_orphans {
+                    table.retain_orphans();
+                }
+                if let Some(format) = format {
+                    // Keyed in the order of the table, which is that of `--sort`.
+                    let counts = table
+                        .rows()
+                        .iter()
+                        .map(|row| (row.index, row.references))
+                        .collect::<IndexMap<_, _>>();
+                    match output_opts.resolve(lock_file) {
+                        (Output::Stdout, _) => documents.write(lock_file, counts)?,
+                        (output, overwrite) => serialize_to_output(
+                            counts,
+                            format,
+                            output,
+                            overwrite,
+                            output_opts.backup_opts.suffix(),
+                            pretty,
+                        )?,
+                    }
+                } else {
+                    logln!(:bold :bright_magenta "Flake input nodes' reference counts:"; &table)
+                }
+                Ok(format!(
+                    "{} of {} nodes",
+                    table.rows().len(),
+                    node_hits.len()
+                ))
+            });
+            documents.finish().unwrap_or_else(|e| exit_with_error(e));
+            exit_on_failure(succeeded)
+        }
+        Command::Stats {
+            json,
+            format,
+            pretty,
+            top,
+            recursive,
+            lock_files,
+        } => {
+            let lock_files = resolve_lock_files(lock_files, recursive);
+            let format = format.or(json.then_some(OutputFormat::Json));
+            let documents = Documents::new(&lock_files, format.unwrap_or_default(), pretty);
+            let succeeded = batch::for_each_lock(&lock_files, |lock_file| {
+                let lock = read_flake_lock(lock_file)?;
+                let stats = LockStats::new(&lock, top);
+                if format.is_some() {
+                    documents.write(lock_file, &stats)?;
+                } else {
+                    log!(&stats);
+                }
+                Ok(format!(
+                    "{} nodes, {} extra copies",
+                    stats.nodes(),
+                    stats.extra_copies()
+                ))
+            });
+            documents.finish().unwrap_or_else(|e| exit_with_error(e));
+            exit_on_failure(succeeded)
+        }
+        Command::Watch {
+            no_follows,
+            promote,
+            no_config,
+            pretty,
+            keep_layout,
+            backup_opts,
+            lock_file,
+        } => {
+            let exclude = Config::exclusions_for(&Input::from(&lock_file), no_config)
+                .unwrap_or_else(|e| exit_with_error(e));
+            elogln!(:bold :bright_magenta "Watching", :green .("'{}'", lock_file.display()), :bright_magenta "for changes.");
+            let res = watch::watch_file(&lock_file, |contents| {
+                let mut lock = match parse_flake_lock(contents) {
+                    Ok(lock) => lock,
+                    Err(e) => {
+                        elogln!(@quiet :bold :red "Skipping invalid lock:", (e));
+                        return None;
+                    }
+                };
+
+                let PruneOutcome {
+                    promoted,
+                    redirections,
+                    removed,
+                    ..
+                } = prune_lock(&mut lock, no_follows, promote, &exclude);
+                elogln!();
+                if !promoted.is_empty() {
+                    elog_promoted_inputs(&promoted);
+                    elogln!();
+                }
+
+                if redirections.is_empty() && promoted.is_empty() && removed.is_empty() {
+                    elogln!(:bold :bright_green "Nothing to prune, the lock was left as-is.");
+                    return None;
+                }
+                let layout = if keep_layout {
+                    JsonLayout::detect(&String::from_utf8_lossy(contents))
+                } else {
+                    JsonLayout::from_pretty(pretty)
+                };
+                let json =
+                    serialize_to_json_string(&lock, &layout).unwrap_or_else(|e| exit_with_error(e));
+                if json.as_bytes() == contents {
+                    elogln!(:bold :bright_green "Nothing to prune, the lock was left as-is.");
+                    return None;
+                }
+                write_display_output(&json, Output::from(&lock_file), true, backup_opts.suffix())
+                    .unwrap_or_else(|e| exit_with_error(e));
+                let removed = removed
+                    .into_iter()
+                    .map(|(index, _)| index)
+                    .collect::<Vec<_>>();
+                elogln!(
+                    :bold (
+                        :bright_green "Redirected", :bright_cyan (redirections.len()),
+                        :bright_green "edges and pruned", :bright_cyan (removed.len()), :bright_green "nodes:"
+                    ),
+                    :red (removed.join(", "))
+                );
+                Some(json.into_bytes())
+            });
+            if let Err(e) = res {
+                exit_with_error(format!("Failed while watching the lock file: {e}"))
+            }
+        }
+        Command::Check {
+            flake_nix,
+            recursive,
+            lock_files,
+        } => {
+            let lock_files = resolve_lock_files(lock_files, recursive);
+            if lock_files.len() > 1 && flake_nix.is_some() {
+                exit_with_error(
+                    "Checking several lock files cannot compare them with a single `--flake-nix`",
+                )
+            }
+            let succeeded = batch::for_each_lock(&lock_files, |lock_file| {
+                let flake_nix = flake_nix.clone().unwrap_or_else(|| match lock_file {
+                    Input::File(path) => path.with_file_name("flake.nix"),
+                    Input::Stdin => PathBuf::from("./flake.nix"),
+                });
+                let lock = read_flake_lock(lock_file)?;
+                let decls = read_flake_nix(&flake_nix)?;
+
+                let mismatches = compare_flake_inputs(&lock, &decls);
+                elog_mismatches(&mismatches);
+                let drifted = mismatches.iter().filter(|m| m.is_drift()).count();
+                let redundant = mismatches.len() - drifted;
+                if drifted > 0 {
+                    return Err(format!(
+                        "The lock has drifted from `flake.nix` in {drifted} place(s)."
+                    ));
+                } else if redundant > 0 {
+                    elogln!(:bold :bright_yellow "The lock agrees with `flake.nix`, with {redundant} redundant follows.");
+                } else {
+                    elogln!(:bold :bright_green "The lock agrees with `flake.nix`.");
+                }
+                Ok(format!("{redundant} redundant follows"))
+            });
+            exit_on_failure(succeeded)
+        }
+        Command::Info {
+            json,
+            format,
             pretty,
             lock_file,
-            output_opts:
-                OutputOptions {
-                    in_place: _,
-                    overwrite,
+        } => {
+            let lock = read_flake_lock(&lock_file).unwrap_or_else(|e| exit_with_error(e));
+            let info = info::LockInfo::new(&lock);
+            match format.or(json.then_some(OutputFormat::Json)) {
+                Some(OutputFormat::Csv) => serialize_to_output(
+                    info.rows(),
+                    OutputFormat::Csv,
+                    Output::Stdout,
+                    true,
+                    None,
+                    pretty,
+                ),
+                Some(format) => {
+                    serialize_to_output(&info, format, Output::Stdout, true, None, pretty)
+                }
+                None => {
+                    log!(&info);
+                    Ok(())
+                }
+            }
+            .unwrap_or_else(|e| exit_with_error(e))
+        }
+        Command::Sbom {
+            format,
+            pretty,
+            overwrite,
+            output,
+            lock_file,
+        } => {
+            let lock = read_flake_lock(&lock_file).unwrap_or_else(|e| exit_with_error(e));
+            // The flake is named after the directory which contains it.
+            let name = match &lock_file {
+                Input::File(path) => std::path::absolute(path).ok().and_then(|path| {
+                    Some(path.parent()?.file_name()?.to_string_lossy().into_owned())
+                }),
+                Input::Stdin => None,
+            };
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("the clock to be after the Unix epoch")
+                .as_secs();
+            let sbom = Sbom::new(&lock, name.as_deref().unwrap_or("flake"), now);
+            serialize_to_output(
+                sbom.to_value(format),
+                OutputFormat::Json,
+                output,
+                overwrite,
+                None,
+                pretty,
+            )
+            .unwrap_or_else(|e| exit_with_error(e));
+            elogln!(:bold :bright_green .("Listed {} locked inputs.", sbom.components()));
+        }
+        Command::Age {
+            max_age,
+            max_spread,
+            fail_on_stale,
+            json,
+            format,
+            pretty,
+            recursive,
+            lock_files,
+        } => {
+            let lock_files = resolve_lock_files(lock_files, recursive);
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("the clock to be after the Unix epoch")
+                .as_secs();
+            let format = format.or(json.then_some(OutputFormat::Json));
+            let documents = Documents::new(&lock_files, format.unwrap_or_default(), pretty);
+            let succeeded = batch::for_each_lock(&lock_files, |lock_file| {
+                let lock = read_flake_lock(lock_file)?;
+                let report = AgeReport::new(&lock, now, max_age, max_spread);
+                if format == Some(OutputFormat::Csv) {
+                    documents.write(lock_file, report.nodes())?;
+                } else if format.is_some() {
+                    documents.write(lock_file, &report)?;
+                } else {
+                    logln!(:bold :bright_magenta "Locked inputs by age:"; &report);
+                }
+                age::elog_flagged(&report);
+                let stale = report.nodes().iter().filter(|row| row.stale).count();
+                let behind = report
+                    .nodes()
+                    .iter()
+                    .filter(|row| row.behind.is_some())
+                    .count();
+                if stale == 0 && behind == 0 {
+                    elogln!(:bold :bright_green "Every input is up to date.");
+                    return Ok(format!("{} inputs", report.nodes().len()));
+                }
+                let message = format!(
+                    "{stale} input(s) are stale, and {behind} are behind another copy of their source."
+                );
+                if fail_on_stale {
+                    return Err(message);
+                }
+                elogln!(:bold :yellow (message));
+                Ok(format!(
+                    "{} inputs, {stale} stale, {behind} behind",
+                    report.nodes().len()
+                ))
+            });
+            documents.finish().unwrap_or_else(|e| exit_with_error(e));
+            exit_on_failure(succeeded)
+        }
+        Command::Policy {
+            allowed_owners,
+            forbid_path,
+            require_nar_hash,
+            forbid_indirect,
+            https_only,
+            recursive,
+            lock_files,
+        } => {
+            let policy = Policy {
+                allowed_owners,
+                forbid_path,
+                require_nar_hash,
+                forbid_indirect,
+                https_tarballs: https_only,
+            };
+            if policy.allowed_owners.is_empty()
+                && !(forbid_path || require_nar_hash || forbid_indirect || https_only)
+            {
+                exit_with_error("No rules were given, see `allfollow policy --help`")
+            }
+            let lock_files = resolve_lock_files(lock_files, recursive);
+            let succeeded = batch::for_each_lock(&lock_files, |lock_file| {
+                let lock = read_flake_lock(lock_file)?;
+                let offenses = policy.check(&lock);
+                policy::elog_offenses(&offenses);
+                if !offenses.is_empty() {
+                    return Err(format!("{} node(s) break the policy.", offenses.len()));
+                }
+                elogln!(:bold :bright_green "Every input complies with the policy.");
+                Ok("compliant".to_owned())
+            });
+            exit_on_failure(succeeded)
+        }
+        Command::Lint {
+            recursive,
+            lock_files,
+        } => {
+            let lock_files = resolve_lock_files(lock_files, recursive);
+            let succeeded = batch::for_each_lock(&lock_files, |lock_file| {
+                // Locks which other commands refuse to read are reported as problems.
+                let lock = lock_file
+                    .open()
+                    .map_err(|e| format!("Failed to read the input file: {e}"))
+                    .and_then(deserialize_flake_lock)?;
+                let problems = lint::lint_lock(&lock);
+                elog_problems(&problems);
+                let errors = problems.iter().filter(|p| p.is_error()).count();
+                let warnings = problems.len() - errors;
+                if errors > 0 {
+                    return Err(format!(
+                        "The lock has {errors} error(s) and {warnings} warning(s)."
+                    ));
+                } else if warnings > 0 {
+                    elogln!(:bold :bright_yellow "The lock is valid, with {warnings} warning(s).");
+                } else {
+                    elogln!(:bold :bright_green "The lock is valid.");
+                }
+                Ok(format!("{warnings} warnings"))
+            });
+            exit_on_failure(succeeded)
+        }
+        Command::Repair {
+            pretty,
+            output_opts,
+            recursive,
+            lock_files,
+        } => {
+            let lock_files = resolve_lock_files(lock_files, recursive);
+            if lock_files.len() > 1 && !output_opts.in_place {
+                exit_with_error("Repairing several lock files requires `--in-place`")
+            }
+            let succeeded = batch::for_each_lock(&lock_files, |lock_file| {
+                let mut lock = lock_file
+                    .open()
+                    .map_err(|e| format!("Failed to read the input file: {e}"))
+                    .and_then(deserialize_flake_lock)?;
+
+                elogln!();
+                elogln!(:bold :bright_magenta "Repairing edges which do not resolve.");
+                let (repaired, unfixed) = repair::repair_dangling_edges(&lock);
+                for RepairedEdge {
+                    node,
+                    input,
+                    old,
+                    new,
+                } in &repaired
+                {
+                    elogln!("-", :yellow .("'{node}/{input}'"), "now", (describe_edge(new)), :dimmed "(was '{old}')");
+                }
+                elogln!();
+                let removed = prune_orphan_nodes(&mut lock);
+                elogln!();
+
+                let (output, overwrite) = output_opts.resolve(lock_file);
+                serialize_to_output(
+                    &lock,
+                    OutputFormat::Json,
                     output,
-                },
+                    overwrite,
+                    output_opts.backup_opts.suffix(),
+                    pretty,
+                )?;
+
+                if !unfixed.is_empty() {
+                    elog_problems(&unfixed);
+                    return Err(format!(
+                        "Repaired {} edges, but {} could not be repaired.",
+                        repaired.len(),
+                        unfixed.len()
+                    ));
+                }
+                Ok(format!(
+                    "repaired {} edges, removed {} nodes",
+                    repaired.len(),
+                    removed.len()
+                ))
+            });
+            exit_on_failure(succeeded)
+        }
+        Command::Merge {
+            prefer,
+            no_follows,
+            no_config,
+            pretty,
+            overwrite,
+            output,
+            base,
+            ours,
+            theirs,
+        } => {
+            let [base_lock, ours_lock, theirs_lock] = [&base, &ours, &theirs].map(|path| {
+                read_flake_lock(&Input::from(path)).unwrap_or_else(|e| exit_with_error(e))
+            });
+
+            elogln!();
+            elogln!(:bold :bright_magenta "Merging locks by input path.");
+            let (mut lock, conflicts) =
+                merge::merge_locks(&base_lock, &ours_lock, &theirs_lock, prefer);
+            for Conflict { path, taken } in &conflicts {
+                elogln!("-", :yellow .("'{}'", path.join("/")), "was changed on both sides, took", :bold :bright_cyan "{taken}");
+            }
+            elogln!(
+                "Merged with", :bold :bright_cyan (conflicts.len()), "conflicting inputs."
+            );
+
+            let exclude = Config::exclusions_for(&Input::from(&ours), no_config)
+                .unwrap_or_else(|e| exit_with_error(e));
+            prune_lock(&mut lock, no_follows, false, &exclude);
+            elogln!();
+
+            let problems = lint::lint_lock(&lock);
+            if problems.iter().any(|problem| problem.is_error()) {
+                elog_problems(&problems);
+                exit_with_error("The merged lock is invalid, it has not been written.")
+            }
+
+            let (output, overwrite) = match output {
+                Some(output) => (output, overwrite),
+                None => (Output::from(&ours), true),
+            };
+            serialize_to_output(&lock, OutputFormat::Json, output, overwrite, None, pretty)
+                .unwrap_or_else(|e| exit_with_error(e));
+        }
+        Command::SetFollows {
+            pretty,
+            keep_layout,
+            output_opts,
+            input,
+            target,
+            lock_file,
+        } => edit_lock(&lock_file, &output_opts, pretty, keep_layout, |lock| {
+            let path = edit::parse_input_path(&input)?;
+            let target = edit::parse_input_path(&target)?;
+            let old = edit::set_follows(lock, &path, &target)?;
+            elogln!("-", :yellow "'{input}'", "now follows", :green .("'{}'", target.join("/")), :dimmed "(was '{old}')");
+            Ok(())
+        }),
+        Command::Unfollow {
+            pretty,
+            keep_layout,
+            output_opts,
+            input,
+            lock_file,
+        } => edit_lock(&lock_file, &output_opts, pretty, keep_layout, |lock| {
+            let path = edit::parse_input_path(&input)?;
+            let old = edit::unfollow(lock, &path)?;
+            let index = lock.follow_path(&path).expect("the input to resolve");
+            elogln!("-", :yellow "'{input}'", "now references", :italic :purple "'{index}'", :dimmed "(was '{old}')");
+            Ok(())
+        }),
+        Command::RemoveInput {
+            pretty,
+            keep_layout,
+            output_opts,
+            input,
+            lock_file,
+        } => edit_lock(&lock_file, &output_opts, pretty, keep_layout, |lock| {
+            let path = edit::parse_input_path(&input)?;
+            let old = edit::remove_input(lock, &path)?;
+            elogln!("-", :yellow "'{input}'", "was removed", :dimmed "(was '{old}')");
+            Ok(())
+        }),
+        Command::Graft {
+            pretty,
+            keep_layout,
+            output_opts,
+            from,
+            input,
+            to,
+            lock_file,
+        } => {
+            let other = read_flake_lock(&Input::from(&from)).unwrap_or_else(|e| exit_with_error(e));
+            edit_lock(&lock_file, &output_opts, pretty, keep_layout, |lock| {
+                let from_path = edit::parse_input_path(&input)?;
+                let to = to.as_deref().unwrap_or(&input);
+                let path = edit::parse_input_path(to)?;
+                let (old, copied) = edit::graft(lock, &path, &other, &from_path)?;
+                for (source, copy) in &copied {
+                    elogln!(@verbose "- copied", :yellow "'{source}'", "as", :italic :purple "'{copy}'");
+                }
+                elogln!("Copied", :bold :bright_cyan (copied.len()), "nodes from", :green .("'{}'", from.display()) ".");
+                let index = lock.follow_path(&path).expect("the input to resolve");
+                elogln!("-", :yellow "'{to}'", "now references", :italic :purple "'{index}'", :dimmed "(was '{old}')");
+                Ok(())
+            })
+        }
+        Command::Pin {
+            pretty,
+            keep_layout,
+            output_opts,
+            rev,
+            nar_hash,
+            last_modified,
+            redirect,
+            source,
+            lock_file,
+        } => {
+            let pin =
+                pin::Pin::new(rev, nar_hash, last_modified).unwrap_or_else(|e| exit_with_error(e));
+            edit_lock(&lock_file, &output_opts, pretty, keep_layout, |lock| {
+                let indices = pin::matching_nodes(lock, &source);
+                if indices.is_empty() {
+                    return Err(format!("No node has the source '{source}'"));
+                }
+                elogln!("Inputs with the source", :green "'{source}'" ":");
+                for path in pin::affected_paths(lock, &indices) {
+                    elogln!("-", :yellow .("'{}'", path.join("/")));
+                }
+                let pinned = if redirect {
+                    let (kept, redirected) = pin::redirect_to_one(lock, &indices);
+                    elogln!("Redirected", :bold :bright_cyan (redirected), "edges to", :italic :purple "'{kept}'" ".");
+                    // The other copies are removed now, so that they are not pinned,
+                    // and none are left at their old revision.
+                    elogln!();
+                    prune_orphan_nodes(lock);
+                    elogln!();
+                    indices
+                        .into_iter()
+                        .filter(|index| lock.get_node(index).is_some())
+                        .collect()
+                } else {
+                    indices
+                };
+                pin::pin_nodes(lock, &pinned, &pin);
+                elogln!("Pinned", :bold :bright_cyan (pinned.len()), "nodes to", :green .("'{}'", pin.rev) ".");
+                Ok(())
+            })
+        }
+        Command::Verify {
+            input,
+            encoding,
+            path,
+            lock_file,
+        } => {
+            let hash = nar::hash_path(&path).unwrap_or_else(|e| {
+                exit_with_error(format!("Failed to hash `{}`: {e}", path.display()))
+            });
+            logln!((hash.encode(encoding)));
+            if let Some(input) = input {
+                let lock = read_flake_lock(&lock_file).unwrap_or_else(|e| exit_with_error(e));
+                let input_path =
+                    edit::parse_input_path(&input).unwrap_or_else(|e| exit_with_error(e));
+                let node = lock
+                    .follow_path(&input_path)
+                    .and_then(|index| lock.get_node(index))
+                    .unwrap_or_else(|| {
+                        exit_with_error(format!("The input '{input}' does not resolve to a node"))
+                    });
+                let expected = node
+                    .locked()
+                    .and_then(|locked| locked.get_str("narHash"))
+                    .unwrap_or_else(|| {
+                        exit_with_error(format!(
+                            "The input '{input}' is locked without a 'narHash'"
+                        ))
+                    })
+                    .parse::<NarHash>()
+                    .unwrap_or_else(|e| exit_with_error(e));
+                if hash != expected {
+                    elogln!(@quiet :bold :red "error:", "the hash of", :green .("'{}'", path.display()), "does not match the input", :yellow "'{input}'";
+                        "  expected:", (expected.encode(encoding));
+                        "       got:", (hash.encode(encoding)));
+                    std::process::exit(1)
+                }
+                elogln!(:bold :bright_green "The hash matches the input", :yellow "'{input}'" ".");
+            }
+        }
+        Command::InstallHook {
+            pre_commit_config: true,
+            ..
+        } => {
+            log!((hook::PRE_COMMIT_CONFIG))
+        }
+        Command::InstallHook {
+            pre_commit_config: false,
+            overwrite,
+            repo,
+        } => {
+            let path = hook::pre_commit_hook_path(&repo).unwrap_or_else(|e| {
+                exit_with_error(format!("Could not locate the git hooks directory: {e}"))
+            });
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).unwrap_or_else(|e| {
+                    exit_with_error(format!("Could not create the git hooks directory: {e}"))
+                });
+            }
+            write_display_output(hook::PRE_COMMIT_HOOK, Output::from(&path), overwrite, None)
+                .unwrap_or_else(|e| exit_with_error(e));
+            hook::make_executable(&path).unwrap_or_else(|e| {
+                exit_with_error(format!("Could not make the hook executable: {e}"))
+            });
+            elogln!(:bold :bright_green "Installed the pre-commit hook to", :green .("'{}'", path.display()));
+        }
+        Command::EmitNix {
+            promote,
+            no_config,
+            overwrite,
+            output,
+            lock_file,
+        } => {
+            let mut lock = read_flake_lock(&lock_file).unwrap_or_else(|e| exit_with_error(e));
+            let exclude = Config::exclusions_for(&lock_file, no_config)
+                .unwrap_or_else(|e| exit_with_error(e));
+
+            let PruneOutcome {
+                promoted,
+                redirections,
+                ..
+            } = prune_lock(&mut lock, false, promote, &exclude);
+            elogln!();
+
+            let declarations = FollowsDeclarations::new(&promoted, &redirections);
+            write_display_output(declarations, output, overwrite, None)
+                .unwrap_or_else(|e| exit_with_error(e))
+        }
+        Command::Review {
+            no_follows,
+            pretty,
+            keep_layout,
+            backup_opts,
+            lock_file,
         } => {
-            let lock = read_flake_lock(lock_file);
-            let node_hits = FlakeNodeVisits::count_from_index(&lock, lock.root_index());
-            if json {
-                serialize_to_json_output(&*node_hits, output, overwrite, pretty)
+            if lock_file == Input::Stdin {
+                exit_with_error(
+                    "Reviewing a lock requires a file to save it to, not standard input",
+                )
+            }
+            if !io::stderr().is_terminal() {
+                exit_with_error("Reviewing a lock requires a terminal")
+            }
+            let (lock, layout) =
+                read_flake_lock_and_layout(&lock_file).unwrap_or_else(|e| exit_with_error(e));
+            let layout = if keep_layout {
+                layout
             } else {
-                logln!(:bold :bright_magenta "Flake input nodes' reference counts:"; &node_hits)
+                JsonLayout::from_pretty(pretty)
+            };
+            let config_path = Config::path_for(&lock_file);
+            let mut config = Config::load(&config_path).unwrap_or_else(|e| exit_with_error(e));
+            let exclude = config
+                .excluded_paths()
+                .unwrap_or_else(|e| exit_with_error(e));
+
+            let mut review = Review::new(&lock, no_follows, &exclude);
+            if review.candidates().is_empty() {
+                elogln!(:bold :bright_green "Nothing to review,", "no inputs would be redirected.");
+                return;
+            }
+            let save = review::run(&mut review, &lock_file.to_string()).unwrap_or_else(|e| {
+                exit_with_error(format!("Failed while drawing the review: {e}"))
+            });
+            if !save {
+                elogln!(:bold :yellow "Nothing was saved.");
+                return;
             }
+
+            let (pruned, removed) = review.outcome();
+            let json =
+                serialize_to_json_string(&pruned, &layout).unwrap_or_else(|e| exit_with_error(e));
+            write_display_output(json, Output::from(lock_file), true, backup_opts.suffix())
+                .unwrap_or_else(|e| exit_with_error(e));
+            config.exclude = review
+                .exclusions()
+                .iter()
+                .map(|path| path.join("/"))
+                .collect();
+            config
+                .save(&config_path)
+                .unwrap_or_else(|e| exit_with_error(e));
+
+            let accepted = review.candidates().iter().filter(|c| c.accepted).count();
+            elogln!(
+                :bold :bright_green "Saved the lock,",
+                .("accepting {accepted} of {} redirections and removing {} nodes.", review.candidates().len(), removed.len())
+            );
+            elogln!("Excluded", .("{} inputs in", config.exclude.len()), :yellow .("'{}'", config_path.display()));
         }
     }
 }
 
-fn read_flake_lock(lock_file: Input) -> LockFile {
+/// Apply `edit` to the lock, remove the nodes which it orphaned, and write the
+/// lock if the edit did not introduce any errors.
+fn edit_lock(
+    lock_file: &Input,
+    output_opts: &OutputOptions,
+    pretty: bool,
+    keep_layout: bool,
+    edit: impl FnOnce(&mut LockFile) -> Result<(), String>,
+) {
+    let (mut lock, layout) =
+        read_flake_lock_and_layout(lock_file).unwrap_or_else(|e| exit_with_error(e));
+    let layout = if keep_layout {
+        layout
+    } else {
+        JsonLayout::from_pretty(pretty)
+    };
+    let before = lint::lint_lock(&lock);
+
+    elogln!();
+    elogln!(:bold :bright_magenta "Editing the lock.");
+    edit(&mut lock).unwrap_or_else(|e| exit_with_error(e));
+    elogln!();
+    prune_orphan_nodes(&mut lock);
+    elogln!();
+
+    let errors = edit::new_errors(&lock, &before);
+    if !errors.is_empty() {
+        elog_problems(&errors);
+        exit_with_error("The edit would break the lock, it has not been written.")
+    }
+
+    let (output, overwrite) = output_opts.resolve(lock_file);
+    let json = serialize_to_json_string(&lock, &layout).unwrap_or_else(|e| exit_with_error(e));
+    write_display_output(json, output, overwrite, output_opts.backup_opts.suffix())
+        .unwrap_or_else(|e| exit_with_error(e))
+}
+
+/// Expand the `INPUT` arguments into lock files, see [`batch::resolve_inputs`].
+fn resolve_lock_files(lock_files: Vec<Input>, recursive: bool) -> Vec<Input> {
+    batch::resolve_inputs(lock_files, recursive).unwrap_or_else(|e| exit_with_error(e))
+}
+
+fn exit_on_failure(succeeded: bool) {
+    if !succeeded {
+        std::process::exit(1)
+    }
+}
+
+/// Print the error and exit unsuccessfully.
+///
+/// Release builds abort on panic without running destructors, so errors are
+/// reported this way instead, after any output has been finished or discarded.
+fn exit_with_error(e: impl Display) -> ! {
+    elogln!(@quiet :bold :red "error:", (e));
+    std::process::exit(1)
+}
+
+fn read_flake_lock(lock_file: &Input) -> Result<LockFile, String> {
     let reader = lock_file
         .open()
-        .unwrap_or_else(|e| panic!("Failed to read the input file: {e}"));
+        .map_err(|e| format!("Failed to read the input file: {e}"))?;
+    parse_flake_lock(reader)
+}
+
+/// Read the lock, and also the layout of its JSON, see [`JsonLayout::detect`].
+fn read_flake_lock_and_layout(lock_file: &Input) -> Result<(LockFile, JsonLayout), String> {
+    let mut text = String::new();
+    lock_file
+        .open()
+        .and_then(|mut reader| reader.read_to_string(&mut text))
+        .map_err(|e| format!("Failed to read the input file: {e}"))?;
+    Ok((
+        parse_flake_lock(text.as_bytes())?,
+        JsonLayout::detect(&text),
+    ))
+}
+
+/// Deserialize the lock, failing if its root node is missing or an edge
+/// references a node which does not exist, which every command but `lint`
+/// and `repair` relies on.
+fn parse_flake_lock(reader: impl Read) -> Result<LockFile, String> {
+    let lock = deserialize_flake_lock(reader)?;
+    let unsound = lint::lint_lock(&lock).into_iter().find(|problem| {
+        matches!(
+            problem,
+            lint::Problem::MissingRoot { .. } | lint::Problem::DanglingIndex { .. }
+        )
+    });
+    if let Some(problem) = unsound {
+        return Err(format!(
+            "At `{}`, {problem}, see `allfollow lint`",
+            problem.json_path()
+        ));
+    }
+    Ok(lock)
+}
+
+fn deserialize_flake_lock(reader: impl Read) -> Result<LockFile, String> {
     let deserializer = &mut serde_json::Deserializer::from_reader(reader);
 
-    let lock: LockFile = {
-        serde_path_to_error::deserialize(deserializer)
-            .unwrap_or_else(|e| panic!("Failed to deserialize the provided flake lock: {e}"))
-    };
+    let lock: LockFile = serde_path_to_error::deserialize(deserializer)
+        .map_err(|e| format!("Failed to deserialize the provided flake lock: {e}"))?;
 
-    if lock.version() < MIN_SUPPORTED_LOCK_VERSION && lock.version() > MAX_SUPPORTED_LOCK_VERSION {
-        panic!(
+    if lock.version() < MIN_SUPPORTED_LOCK_VERSION || lock.version() > MAX_SUPPORTED_LOCK_VERSION {
+        return Err(format!(
             "This program supports lock files between schema versions {} and {} while the flake you have asked to modify is of version {}.",
             MIN_SUPPORTED_LOCK_VERSION,
             MAX_SUPPORTED_LOCK_VERSION,
             lock.version()
-        );
+        ));
     }
 
-    lock
+    Ok(lock)
+}
+
+fn read_flake_nix(path: &std::path::Path) -> Result<InputDecls, String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read `{}`: {e}", path.display()))?;
+    InputDecls::parse_flake(&source)
+        .map_err(|e| format!("Failed to parse `{}`: {e}", path.display()))
 }
 
-fn serialize_to_json_output(value: impl Serialize, output: Output, overwrite: bool, pretty: bool) {
-    let writer = output
-        .create(!overwrite)
-        .unwrap_or_else(|e| panic!("Could not write to output: {e}"));
+fn serialize_to_output(
+    value: impl Serialize,
+    format: OutputFormat,
+    output: Output,
+    overwrite: bool,
+    backup_suffix: Option<&str>,
+    pretty: bool,
+) -> Result<(), String> {
+    let mut writer = output
+        .create(!overwrite, backup_suffix)
+        .map_err(|e| format!("Could not write to output: {e}"))?;
+
+    if let Err(e) = format.write(value, &mut writer, pretty) {
+        let _ = writer.discard();
+        return Err(format!(
+            "Failed while serializing to output, it has been left untouched: {e}"
+        ));
+    }
+    writer
+        .finish()
+        .map_err(|e| format!("Failed while replacing the output, it has been left untouched: {e}"))
+}
 
-    let res = if pretty {
-        value.serialize(&mut Serializer::pretty(writer))
+/// Everything which was changed by [`prune_lock`].
+struct PruneOutcome {
+    promoted: Vec<PromotedInput>,
+    redirections: Vec<Redirection>,
+    skipped: Vec<SkippedEdge>,
+    /// The indices of nodes which were removed, and the nodes themselves.
+    removed: Vec<(String, Node)>,
+}
+
+/// Imitate follows behavior throughout the lock, optionally promoting
+/// shared inputs first, and then remove the nodes which became orphaned.
+/// The edges at the input paths `exclude` are left as they are.
+fn prune_lock(
+    lock: &mut LockFile,
+    indexed: bool,
+    promote: bool,
+    exclude: &[Vec<String>],
+) -> PruneOutcome {
+    let (promoted, mut redirections, mut skipped) = if promote {
+        elogln!();
+        promote_shared_inputs(lock, indexed, exclude)
     } else {
-        value.serialize(&mut Serializer::new(writer))
+        Default::default()
     };
 
-    if let Err(e) = res {
-        panic!("Failed while serializing to output, file is probably corrupt: {e}")
+    elogln!();
+    let (substituted, unsubstituted) = substitute_flake_inputs_with_follows(lock, indexed, exclude);
+    redirections.extend(substituted);
+    skipped.extend(unsubstituted);
+    elogln!();
+    let removed = prune_orphan_nodes(lock);
+
+    PruneOutcome {
+        promoted,
+        redirections,
+        skipped,
+        removed,
+    }
+}
+
+/// An edge of a node which was replaced while imitating follows behavior.
+#[derive(Clone, Debug)]
+pub struct Redirection {
+    /// Index of the node which owns the edge.
+    pub node: String,
+    /// The input names taken from the root to reach the edge,
+    /// the last of which is the name of the edge itself.
+    pub path: Vec<String>,
+    pub old: NodeEdge,
+    pub new: NodeEdge,
+}
+
+/// An edge which was left as-is while imitating follows behavior.
+#[derive(Clone, Debug)]
+pub struct SkippedEdge {
+    /// Index of the node which owns the edge.
+    pub node: String,
+    /// The input names taken from the root to reach the edge.
+    pub path: Vec<String>,
+    pub edge: NodeEdge,
+    /// Why the edge could not be redirected.
+    pub reason: String,
+}
+
+fn serialize_to_json_string(value: impl Serialize, layout: &JsonLayout) -> Result<String, String> {
+    let mut json = Vec::new();
+    layout
+        .write(value, &mut json)
+        .map_err(|e| format!("Failed while serializing: {e}"))?;
+    Ok(String::from_utf8(json).expect("JSON to be valid UTF-8"))
+}
+
+fn write_display_output(
+    value: impl Display,
+    output: Output,
+    overwrite: bool,
+    backup_suffix: Option<&str>,
+) -> Result<(), String> {
+    let mut writer = output
+        .create(!overwrite, backup_suffix)
+        .map_err(|e| format!("Could not write to output: {e}"))?;
+
+    if let Err(e) = write!(writer, "{value}") {
+        let _ = writer.discard();
+        return Err(format!(
+            "Failed while writing to output, it has been left untouched: {e}"
+        ));
     }
+    writer
+        .finish()
+        .map_err(|e| format!("Failed while writing to output, it has been left untouched: {e}"))
 }
 
-fn substitute_flake_inputs_with_follows(lock: &LockFile, indexed: bool) {
+fn substitute_flake_inputs_with_follows(
+    lock: &LockFile,
+    indexed: bool,
+    exclude: &[Vec<String>],
+) -> (Vec<Redirection>, Vec<SkippedEdge>) {
     elogln!(:bold :bright_magenta "Redirecting inputs to imitate follows behavior.");
 
+    let mut redirections = Vec::new();
+    let mut skipped = Vec::new();
     let root = lock.root().expect(EXPECT_ROOT_EXIST);
     for (input_name, input_index) in root
         .iter_edges()
         .filter_map(|(name, edge)| edge.index().map(|index| (name, index)))
     {
-        elogln!(:bold (:bright_cyan "Replacing inputs for", :green "'{input_name}'"), :dimmed "(" :dimmed :italic "'{input_index}'" :dimmed ")");
-        let input = &*lock
-            .get_node(&*input_index)
-            .expect("a node to exist with this index");
-        substitute_node_inputs_with_root_inputs(lock, input, indexed);
+        elogln!(@verbose :bold (:bright_cyan "Replacing inputs for", :green "'{input_name}'"), :dimmed "(" :dimmed :italic "'{input_index}'" :dimmed ")");
+        let (substituted, unsubstituted) = substitute_node_inputs_with_root_inputs(
+            lock,
+            input_name,
+            &input_index,
+            indexed,
+            exclude,
+        );
+        redirections.extend(substituted);
+        skipped.extend(unsubstituted);
     }
+    elogln!(
+        "Redirected", :bold :bright_cyan (redirections.len()), "edges,",
+        :bold :bright_cyan (skipped.len()), "left as-is."
+    );
+    (redirections, skipped)
 }
 
 /// When `indexed == false`, the input replacements all will reference identically
@@ -213,51 +1721,126 @@ fn substitute_flake_inputs_with_follows(lock: &LockFile, indexed: bool) {
 ///
 /// Otherwise, if `indexed == true`, the each input replacement will be cloned
 /// verbatim from the root node, most likely retaining a `NodeEdge::Indexed`.
-fn substitute_node_inputs_with_root_inputs(lock: &LockFile, node: &Node, indexed: bool) {
+fn substitute_node_inputs_with_root_inputs(
+    lock: &LockFile,
+    input_name: &str,
+    input_index: &str,
+    indexed: bool,
+    exclude: &[Vec<String>],
+) -> (Vec<Redirection>, Vec<SkippedEdge>) {
+    let mut redirections = Vec::new();
+    let mut skipped = Vec::new();
     let root = lock.root().expect(EXPECT_ROOT_EXIST);
+    // The root input is dangling, which `repair` may be able to fix.
+    let Some(node) = lock.get_node(input_index) else {
+        return (redirections, skipped);
+    };
     for (edge_name, mut edge) in node.iter_edges_mut() {
+        let path = vec![input_name.to_owned(), edge_name.to_owned()];
+        if exclude.contains(&path) {
+            elogln!(@verbose :bold (:cyan "Excluded", :yellow "'{edge_name}'"), :dimmed "by the configuration");
+            skipped.push(SkippedEdge {
+                node: input_index.to_owned(),
+                path,
+                edge: edge.clone(),
+                reason: "it is excluded by the configuration".to_owned(),
+            });
+            continue;
+        }
         if let Some(root_edge) = root.get_edge(edge_name) {
+            let new = if indexed {
+                (*root_edge).clone()
+            } else {
+                NodeEdge::from_iter([edge_name])
+            };
+            // Edges which are already redirected are left out, so that a pruned lock is unchanged.
+            if *edge == new {
+                continue;
+            }
+            let old = std::mem::replace(&mut *edge, new);
             if indexed {
-                let old = std::mem::replace(&mut *edge, (*root_edge).clone());
-                elogln!("-", :yellow "'{edge_name}'", "now references", :italic :purple "'{edge}'", :dimmed "(was '{old}')");
+                elogln!(@verbose "-", :yellow "'{edge_name}'", "now references", :italic :purple "'{edge}'", :dimmed "(was '{old}')");
             } else {
-                let old = std::mem::replace(&mut *edge, NodeEdge::from_iter([edge_name]));
-                elogln!("-", :yellow "'{edge_name}'", "now follows", :green "'{edge}'", :dimmed "(was '{old}')");
+                elogln!(@verbose "-", :yellow "'{edge_name}'", "now follows", :green "'{edge}'", :dimmed "(was '{old}')");
             }
+            redirections.push(Redirection {
+                node: input_index.to_owned(),
+                path: vec![input_name.to_owned(), edge_name.to_owned()],
+                old,
+                new: edge.clone(),
+            });
         } else {
-            elogln!(
+            // The edge may follow a path through itself, so release it first.
+            let unchanged = edge.clone();
+            drop(edge);
+            // A broken lock may have edges which do not resolve.
+            let target = lock
+                .resolve_edge(&unchanged)
+                .unwrap_or_else(|| format!("unresolved {unchanged}"));
+            elogln!(@verbose
                 :bold (:cyan "No suitable replacement for", :yellow "'{edge_name}'"),
-                :dimmed "(" :dimmed :italic ("'" (lock.resolve_edge(&edge).unwrap()) "'") :dimmed ")"
+                :dimmed "(" :dimmed :italic "'{target}'" :dimmed ")"
             );
+            skipped.push(SkippedEdge {
+                node: input_index.to_owned(),
+                path: vec![input_name.to_owned(), edge_name.to_owned()],
+                edge: unchanged,
+                reason: format!("the root has no input named '{edge_name}'"),
+            });
         }
     }
+    (redirections, skipped)
 }
 
-fn prune_orphan_nodes(lock: &mut LockFile) {
+fn prune_orphan_nodes(lock: &mut LockFile) -> Vec<(String, Node)> {
     elogln!(:bold :bright_magenta "Pruning orphaned nodes from modified lock.");
 
     let node_hits = FlakeNodeVisits::count_from_index(lock, lock.root_index());
 
-    let dead_nodes = node_hits
+    let mut dead_nodes = node_hits
         .into_inner()
         .into_iter()
         .filter(|&(_, count)| count == 0)
         .map(|(index, _)| index.to_owned())
         .collect::<Vec<_>>();
+    dead_nodes.sort_unstable();
 
+    let mut removed = Vec::new();
     for index in dead_nodes {
-        lock.remove_node(&index);
-        elogln!("- removed", :red "'{index}'");
+        let node = lock
+            .remove_node(&index)
+            .expect("a node to exist with this index");
+        elogln!(@verbose "- removed", :red "'{index}'");
+        removed.push((index, node));
     }
+    elogln!("Removed", :bold :bright_cyan (removed.len()), "orphaned nodes.");
+    removed
 }
 
+/// Call `op` with the index of every node along every path from `index`.
+/// Edges which do not resolve, or which lead back to a node on the
+/// current path, are not followed.
 fn recurse_inputs(lock: &LockFile, index: String, op: &mut impl FnMut(String)) {
-    let node = lock.get_node(&index).unwrap();
-    op(index);
-    for (_, edge) in node.iter_edges() {
-        let index = lock.resolve_edge(&edge).unwrap();
-        recurse_inputs(lock, index, op);
+    fn recurse(
+        lock: &LockFile,
+        index: String,
+        stack: &mut Vec<String>,
+        op: &mut impl FnMut(String),
+    ) {
+        let Some(node) = lock.get_node(&index) else {
+            return;
+        };
+        op(index.clone());
+        stack.push(index);
+        for (_, edge) in node.iter_edges() {
+            match lock.resolve_edge(&edge) {
+                Some(index) if !stack.contains(&index) => recurse(lock, index, stack, op),
+                _ => {}
+            }
+        }
+        stack.pop();
     }
+    recurse(lock, index, &mut Vec::new(), op)
 }
 
 struct FlakeNodeVisits<'a> {
@@ -281,6 +1864,13 @@ impl<'a> FlakeNodeVisits<'a> {
     fn into_inner(self) -> HashMap<&'a str, u32> {
         self.inner
     }
+
+    fn to_sorted(&self) -> BTreeMap<String, u32> {
+        self.inner
+            .iter()
+            .map(|(&index, &count)| (index.to_owned(), count))
+            .collect()
+    }
 }
 
 impl<'a> From<FlakeNodeVisits<'a>> for HashMap<&'a str, u32> {
@@ -305,15 +1895,10 @@ impl<'a> std::ops::DerefMut for FlakeNodeVisits<'a> {
 
 impl<'a> std::fmt::Display for FlakeNodeVisits<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let max_pad = {
-            let (mut min_len, mut max_len) = (0, 0);
-            for key in self.inner.keys() {
-                min_len = std::cmp::min(min_len, key.len());
-                max_len = std::cmp::max(max_len, key.len());
-            }
-            max_len - min_len
-        };
-        for (index, count) in self.inner.iter() {
+        let max_pad = self.inner.keys().map(|key| key.len()).max().unwrap_or(0);
+        let mut entries = self.inner.iter().collect::<Vec<_>>();
+        entries.sort_unstable();
+        for (index, count) in entries {
             if index == &self.root_index {
                 f.write_fmt(format_args_colored!(
                     :dimmed .("{:1$}", index, max_pad), :red "=", :dimmed &count;
@@ -0,0 +1,305 @@
+use std::collections::{BTreeSet, HashMap};
+use std::str::FromStr;
+
+use crate::flake_lock::{LockFile, Node, NodeEdge};
+
+/// Which side of a merge to take when both changed the same input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Prefer {
+    /// The side whose input was last modified most recently.
+    #[default]
+    Newer,
+    Ours,
+    Theirs,
+}
+
+impl FromStr for Prefer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "newer" => Ok(Self::Newer),
+            "ours" => Ok(Self::Ours),
+            "theirs" => Ok(Self::Theirs),
+            _ => Err(format!("expected `newer`, `ours` or `theirs`, found `{s}`")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Base,
+    Ours,
+    Theirs,
+}
+
+impl std::fmt::Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Base => write!(f, "base"),
+            Self::Ours => write!(f, "ours"),
+            Self::Theirs => write!(f, "theirs"),
+        }
+    }
+}
+
+/// An input which both sides changed differently, and which side was taken.
+#[derive(Clone, Debug)]
+pub struct Conflict {
+    /// The input names taken from the root to reach the input.
+    pub path: Vec<String>,
+    pub taken: Side,
+}
+
+/// What an edge leads to on one side of the merge. Follows are kept as paths,
+/// because they are resolved by input names, which are the same on every side.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Entry {
+    Follows(Vec<String>),
+    Node(Side, String),
+}
+
+/// Merge the changes which `ours` and `theirs` each made to `base`,
+/// matching nodes by the input paths which reach them rather than by index.
+///
+/// Where only one side changed an input, that change is taken, and where
+/// both did, the whole input is taken from one side according to `prefer`.
+/// The nodes of the merged lock are named after their inputs, as Nix would,
+/// and nodes which become unreachable are not removed.
+/// The merged lock has the schema version of `ours`.
+pub fn merge_locks(
+    base: &LockFile,
+    ours: &LockFile,
+    theirs: &LockFile,
+    prefer: Prefer,
+) -> (LockFile, Vec<Conflict>) {
+    let mut merged = LockFile::new();
+    merged.set_version(ours.version());
+    let mut merger = Merger {
+        locks: [base, ours, theirs],
+        prefer,
+        merged,
+        merged_indices: HashMap::new(),
+        conflicts: Vec::new(),
+    };
+    let entries = [Side::Base, Side::Ours, Side::Theirs]
+        .map(|side| Some(Entry::Node(side, merger.lock(side).root_index().to_owned())));
+    let root_index = merger.merged.root_index().to_owned();
+    merger
+        .merged_indices
+        .insert(entries.clone(), root_index.clone());
+    merger.merge_edges(&root_index, &mut Vec::new(), &entries);
+    (merger.merged, merger.conflicts)
+}
+
+struct Merger<'a> {
+    locks: [&'a LockFile; 3],
+    prefer: Prefer,
+    merged: LockFile,
+    /// The merged node for each combination of nodes which has been merged,
+    /// so that a node reachable by several paths is only merged once.
+    merged_indices: HashMap<[Option<Entry>; 3], String>,
+    conflicts: Vec<Conflict>,
+}
+
+impl Merger<'_> {
+    fn lock(&self, side: Side) -> &LockFile {
+        match side {
+            Side::Base => self.locks[0],
+            Side::Ours => self.locks[1],
+            Side::Theirs => self.locks[2],
+        }
+    }
+
+    /// Merge every edge of the nodes in `entries` into the merged node `index`.
+    fn merge_edges(&mut self, index: &str, path: &mut Vec<String>, entries: &[Option<Entry>; 3]) {
+        let mut names = BTreeSet::new();
+        for entry in entries {
+            if let Some(Entry::Node(side, index)) = entry {
+                if let Some(node) = self.lock(*side).get_node(index) {
+                    names.extend(node.iter_edges().map(|(name, _)| name.to_owned()));
+                }
+            }
+        }
+        for name in names {
+            let children = entries
+                .clone()
+                .map(|entry| entry.and_then(|entry| self.child_entry(&entry, &name)));
+            path.push(name.clone());
+            let edge = self.merge_entries(path, children);
+            path.pop();
+            if let Some(edge) = edge {
+                self.merged
+                    .get_node_mut(index)
+                    .expect("a node to exist with this index")
+                    .insert_edge(name, edge);
+            }
+        }
+    }
+
+    /// Merge one input, given what it is on each side, returning the edge
+    /// which leads to it in the merged lock, if it should exist at all.
+    fn merge_entries(
+        &mut self,
+        path: &mut Vec<String>,
+        entries: [Option<Entry>; 3],
+    ) -> Option<NodeEdge> {
+        let [base, ours, theirs] = &entries;
+        let (taken, entries) = if self.same(ours, theirs) || self.same(base, theirs) {
+            (ours.clone(), entries)
+        } else if self.same(base, ours) {
+            (theirs.clone(), entries)
+        } else {
+            // Changes beneath the input cannot be reconciled with a different
+            // version of it, so the input is taken as a whole.
+            let side = self.pick(ours, theirs);
+            self.conflicts.push(Conflict {
+                path: path.clone(),
+                taken: side,
+            });
+            let taken = if side == Side::Ours { ours } else { theirs }.clone();
+            (taken.clone(), [taken.clone(), taken.clone(), taken])
+        };
+
+        match taken? {
+            Entry::Follows(follows) => Some(NodeEdge::Follows(follows)),
+            Entry::Node(side, source_index) => {
+                if let Some(index) = self.merged_indices.get(&entries) {
+                    return Some(NodeEdge::Indexed(index.clone()));
+                }
+                let mut node = Node::clone(
+                    &self
+                        .lock(side)
+                        .get_node(&source_index)
+                        .expect("a node to exist with this index"),
+                );
+                node.clear_edges();
+                let index = self
+                    .merged
+                    .unused_index(path.last().expect("a path to an input"));
+                self.merged.insert_node(index.clone(), node);
+                self.merged_indices.insert(entries.clone(), index.clone());
+                self.merge_edges(&index, path, &entries);
+                Some(NodeEdge::Indexed(index))
+            }
+        }
+    }
+
+    fn child_entry(&self, entry: &Entry, name: &str) -> Option<Entry> {
+        let Entry::Node(side, index) = entry else {
+            return None;
+        };
+        let lock = self.lock(*side);
+        let edge = lock.get_node(index)?.get_edge(name)?.clone();
+        match edge {
+            NodeEdge::Follows(follows) => Some(Entry::Follows(follows)),
+            // Dangling edges are treated as if they were not there.
+            NodeEdge::Indexed(index) => {
+                lock.get_node(&index)?;
+                Some(Entry::Node(*side, index))
+            }
+        }
+    }
+
+    /// Whether two entries are the same input, regardless of their own inputs.
+    fn same(&self, a: &Option<Entry>, b: &Option<Entry>) -> bool {
+        match (a, b) {
+            (None, None) => true,
+            (Some(Entry::Follows(a)), Some(Entry::Follows(b))) => a == b,
+            (Some(Entry::Node(a_side, a)), Some(Entry::Node(b_side, b))) => {
+                let a = self.lock(*a_side).get_node(a);
+                let b = self.lock(*b_side).get_node(b);
+                match (a.as_deref(), b.as_deref()) {
+                    (Some(a), Some(b)) => {
+                        a.locked() == b.locked()
+                            && a.original() == b.original()
+                            && a.is_flake() == b.is_flake()
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn pick(&self, ours: &Option<Entry>, theirs: &Option<Entry>) -> Side {
+        match self.prefer {
+            Prefer::Ours => Side::Ours,
+            Prefer::Theirs => Side::Theirs,
+            // An input which was removed is older than any which was kept.
+            Prefer::Newer => {
+                if self.last_modified(Side::Theirs, theirs) > self.last_modified(Side::Ours, ours) {
+                    Side::Theirs
+                } else {
+                    Side::Ours
+                }
+            }
+        }
+    }
+
+    fn last_modified(&self, side: Side, entry: &Option<Entry>) -> Option<u64> {
+        let lock = self.lock(side);
+        let index = match entry.as_ref()? {
+            Entry::Follows(follows) => lock.follow_path(follows)?,
+            Entry::Node(_, index) => index.clone(),
+        };
+        lock.get_node(index)?.locked()?.last_modified()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_locks, Prefer, Side};
+    use crate::flake_lock::{LockFile, NodeEdge};
+
+    fn lock(nixpkgs_rev: &str, nixpkgs_modified: u64) -> LockFile {
+        serde_json::from_str(&format!(
+            r#"{{
+                "nodes": {{
+                    "nixpkgs": {{
+                        "locked": {{ "type": "github", "owner": "o", "repo": "nixpkgs", "rev": "{nixpkgs_rev}", "lastModified": {nixpkgs_modified} }},
+                        "original": {{ "type": "github", "owner": "o", "repo": "nixpkgs" }}
+                    }},
+                    "tool": {{
+                        "inputs": {{ "nixpkgs": ["nixpkgs"] }},
+                        "locked": {{ "type": "github", "owner": "o", "repo": "tool", "rev": "a" }},
+                        "original": {{ "type": "github", "owner": "o", "repo": "tool" }}
+                    }},
+                    "root": {{ "inputs": {{ "nixpkgs": "nixpkgs", "tool": "tool" }} }}
+                }},
+                "root": "root",
+                "version": 7
+            }}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn merges() {
+        let base = lock("a", 1);
+        let mut ours = lock("b", 2);
+        ours.set_version(6);
+        let theirs = lock("c", 3);
+        ours.get_node_mut("tool")
+            .unwrap()
+            .insert_edge("systems", NodeEdge::from_iter(["nixpkgs"]));
+
+        let (merged, conflicts) = merge_locks(&base, &ours, &theirs, Prefer::Newer);
+        assert_eq!(merged.version(), 6);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, ["nixpkgs"]);
+        assert_eq!(conflicts[0].taken, Side::Theirs);
+        let nixpkgs = merged.get_node("nixpkgs").unwrap();
+        assert_eq!(nixpkgs.locked().unwrap().rev(), Some("c"));
+        // Only one side added the input, so it is kept.
+        let tool = merged.get_node("tool").unwrap();
+        assert_eq!(
+            *tool.get_edge("systems").unwrap(),
+            NodeEdge::from_iter(["nixpkgs"])
+        );
+
+        let (merged, _) = merge_locks(&base, &ours, &theirs, Prefer::Ours);
+        let nixpkgs = merged.get_node("nixpkgs").unwrap();
+        assert_eq!(nixpkgs.locked().unwrap().rev(), Some("b"));
+    }
+}
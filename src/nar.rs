@@ -0,0 +1,176 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use sha2::{Digest as _, Sha256};
+
+use crate::nar_hash::NarHash;
+
+/// The first string of every NAR, as it is serialized.
+const NAR_MAGIC: &[u8] = b"\x0d\0\0\0\0\0\0\0nix-archive-1\0\0\0";
+
+/// Hash `path` as Nix would for a `narHash`.
+///
+/// A file which is itself a NAR is hashed as it is, and anything else is
+/// serialized as a NAR first, following symlinks only if `path` is one.
+pub fn hash_path(path: &Path) -> io::Result<NarHash> {
+    let mut hasher = Sha256::new();
+    if is_nar_file(path)? {
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+    } else {
+        write_nar(&mut hasher, &fs::canonicalize(path)?)?;
+    }
+    Ok(NarHash::new(hasher.finalize().into()))
+}
+
+/// Serialize `path` as a NAR, without following it if it is a symlink.
+pub fn write_nar(writer: &mut impl Write, path: &Path) -> io::Result<()> {
+    write_str(writer, b"nix-archive-1")?;
+    write_node(writer, path)
+}
+
+fn is_nar_file(path: &Path) -> io::Result<bool> {
+    if !fs::metadata(path)?.is_file() {
+        return Ok(false);
+    }
+    let mut magic = Vec::new();
+    File::open(path)?
+        .take(NAR_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    Ok(magic == NAR_MAGIC)
+}
+
+/// Serialize the file, symlink or directory at `path`, without following it.
+/// Anything else, such as a socket or device, cannot be archived.
+fn write_node(writer: &mut impl Write, path: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !(metadata.is_symlink() || metadata.is_dir() || metadata.is_file()) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "`{}` is not a file, symlink or directory, so cannot be archived",
+                path.display()
+            ),
+        ));
+    }
+    write_str(writer, b"(")?;
+    write_str(writer, b"type")?;
+    if metadata.is_symlink() {
+        write_str(writer, b"symlink")?;
+        write_str(writer, b"target")?;
+        write_str(writer, os_bytes(fs::read_link(path)?.as_os_str()))?;
+    } else if metadata.is_dir() {
+        write_str(writer, b"directory")?;
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<Vec<_>>>()?;
+        // Entries are ordered by the bytes of their names.
+        entries.sort_unstable_by(|a, b| os_bytes(a).cmp(os_bytes(b)));
+        for name in entries {
+            write_str(writer, b"entry")?;
+            write_str(writer, b"(")?;
+            write_str(writer, b"name")?;
+            write_str(writer, os_bytes(&name))?;
+            write_str(writer, b"node")?;
+            write_node(writer, &path.join(&name))?;
+            write_str(writer, b")")?;
+        }
+    } else {
+        write_str(writer, b"regular")?;
+        if is_executable(&metadata) {
+            write_str(writer, b"executable")?;
+            write_str(writer, b"")?;
+        }
+        write_str(writer, b"contents")?;
+        let len = metadata.len();
+        writer.write_all(&len.to_le_bytes())?;
+        let copied = io::copy(&mut File::open(path)?.take(len), writer)?;
+        if copied != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("`{}` changed while it was read", path.display()),
+            ));
+        }
+        write_padding(writer, len)?;
+    }
+    write_str(writer, b")")
+}
+
+/// Write the length of `bytes`, then `bytes` padded to a multiple of eight.
+fn write_str(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    let len = bytes.len() as u64;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(bytes)?;
+    write_padding(writer, len)
+}
+
+fn write_padding(writer: &mut impl Write, len: u64) -> io::Result<()> {
+    let padding = (8 - len % 8) % 8;
+    writer.write_all(&[0; 8][..padding as usize])
+}
+
+#[cfg(unix)]
+fn os_bytes(name: &std::ffi::OsStr) -> &[u8] {
+    use std::os::unix::ffi::OsStrExt as _;
+    name.as_bytes()
+}
+
+#[cfg(not(unix))]
+fn os_bytes(name: &std::ffi::OsStr) -> &[u8] {
+    name.as_encoded_bytes()
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt as _;
+    metadata.permissions().mode() & 0o100 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{hash_path, write_nar};
+
+    #[test]
+    fn archives() {
+        let dir = std::env::temp_dir().join(format!("allfollow-nar-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/hi"), "hi").unwrap();
+
+        let mut nar = Vec::new();
+        write_nar(&mut nar, &dir.join("sub/hi")).unwrap();
+        let expected = [
+            b"\x0d\0\0\0\0\0\0\0nix-archive-1\0\0\0".as_slice(),
+            b"\x01\0\0\0\0\0\0\0(\0\0\0\0\0\0\0",
+            b"\x04\0\0\0\0\0\0\0type\0\0\0\0",
+            b"\x07\0\0\0\0\0\0\0regular\0",
+            b"\x08\0\0\0\0\0\0\0contents",
+            b"\x02\0\0\0\0\0\0\0hi\0\0\0\0\0\0",
+            b"\x01\0\0\0\0\0\0\0)\0\0\0\0\0\0\0",
+        ]
+        .concat();
+        assert_eq!(nar, expected);
+
+        // A NAR file is hashed as it is, so it matches the path it archives.
+        let mut nar = Vec::new();
+        write_nar(&mut nar, &dir.join("sub")).unwrap();
+        fs::write(dir.join("sub.nar"), nar).unwrap();
+        assert_eq!(
+            hash_path(&dir.join("sub.nar")).unwrap(),
+            hash_path(&dir.join("sub")).unwrap()
+        );
+
+        #[cfg(unix)]
+        {
+            let _socket = std::os::unix::net::UnixListener::bind(dir.join("sub/socket")).unwrap();
+            assert!(write_nar(&mut Vec::new(), &dir.join("sub")).is_err());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
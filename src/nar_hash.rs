@@ -0,0 +1,179 @@
+use std::str::FromStr;
+
+/// The digits of the base-32 encoding used by Nix, which omits `e`, `o`, `u` and `t`.
+const BASE32_DIGITS: &[u8; 32] = b"0123456789abcdfghijklmnpqrsvwxyz";
+const BASE64_DIGITS: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A SHA-256 hash of the NAR serialization of a path, such as a `narHash`.
+///
+/// Parsed from an SRI hash such as `sha256-<base64>`, or from base-16 or
+/// Nix base-32 digits with or without a `sha256:` prefix.
+/// Displayed as an SRI hash, which is how Nix writes it in a lock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NarHash([u8; 32]);
+
+/// The ways which Nix writes a hash.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashEncoding {
+    #[default]
+    Sri,
+    Base16,
+    Base32,
+}
+
+impl FromStr for HashEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sri" => Ok(Self::Sri),
+            "base16" => Ok(Self::Base16),
+            "base32" => Ok(Self::Base32),
+            _ => Err(format!("expected `sri`, `base16` or `base32`, found `{s}`")),
+        }
+    }
+}
+
+impl NarHash {
+    pub fn new(digest: [u8; 32]) -> Self {
+        Self(digest)
+    }
+
+    pub fn encode(&self, encoding: HashEncoding) -> String {
+        match encoding {
+            HashEncoding::Sri => format!("sha256-{}", encode_base64(&self.0)),
+            HashEncoding::Base16 => self.0.iter().map(|byte| format!("{byte:02x}")).collect(),
+            HashEncoding::Base32 => encode_base32(&self.0),
+        }
+    }
+}
+
+impl FromStr for NarHash {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("The hash '{s}' is not a valid SHA-256 hash");
+        let bytes = if let Some(base64) = s.strip_prefix("sha256-") {
+            decode_base64(base64)
+        } else {
+            let digits = s.strip_prefix("sha256:").unwrap_or(s);
+            match digits.len() {
+                64 => decode_base16(digits),
+                52 => decode_base32(digits),
+                _ => None,
+            }
+        };
+        let digest = bytes
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .ok_or_else(invalid)?;
+        Ok(Self(digest))
+    }
+}
+
+impl std::fmt::Display for NarHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.encode(HashEncoding::Sri))
+    }
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0_u32, |bits, (i, &byte)| {
+            bits | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64_DIGITS[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let (mut bits, mut len) = (0_u32, 0);
+    for c in text.trim_end_matches('=').bytes() {
+        let value = BASE64_DIGITS.iter().position(|&digit| digit == c)?;
+        bits = bits << 6 | value as u32;
+        len += 6;
+        if len >= 8 {
+            len -= 8;
+            bytes.push((bits >> len) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+fn decode_base16(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Nix writes the least significant digit first, reading the bytes as one
+/// little-endian number, which is not the same as RFC 4648.
+fn encode_base32(bytes: &[u8]) -> String {
+    let len = (bytes.len() * 8).div_ceil(5);
+    (0..len)
+        .rev()
+        .map(|n| {
+            let (i, j) = (n * 5 / 8, n * 5 % 8);
+            let bits = u16::from(bytes[i]) | u16::from(bytes.get(i + 1).copied().unwrap_or(0)) << 8;
+            BASE32_DIGITS[(bits >> j & 0x1f) as usize] as char
+        })
+        .collect()
+}
+
+fn decode_base32(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![0_u8; text.len() * 5 / 8];
+    for (n, c) in text.bytes().rev().enumerate() {
+        let digit = BASE32_DIGITS.iter().position(|&digit| digit == c)? as u16;
+        let (i, j) = (n * 5 / 8, n * 5 % 8);
+        let bits = digit << j;
+        *bytes.get_mut(i)? |= bits as u8;
+        match bytes.get_mut(i + 1) {
+            Some(byte) => *byte |= (bits >> 8) as u8,
+            // The digits may not encode more bits than the hash has.
+            None if bits >> 8 != 0 => return None,
+            None => {}
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HashEncoding, NarHash};
+
+    #[test]
+    fn encodings() {
+        // The hash of the empty string, as given by `nix hash convert`.
+        let sri = "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+        let base16 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let base32 = "0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73";
+
+        let hash = sri.parse::<NarHash>().unwrap();
+        assert_eq!(hash.encode(HashEncoding::Base16), base16);
+        assert_eq!(hash.encode(HashEncoding::Base32), base32);
+        assert_eq!(hash.to_string(), sri);
+        assert_eq!(base16.parse(), Ok(hash));
+        assert_eq!(format!("sha256:{base32}").parse(), Ok(hash));
+
+        assert!("sha256-47DEQpj8".parse::<NarHash>().is_err());
+        assert!(
+            "sha256:0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c7e"
+                .parse::<NarHash>()
+                .is_err()
+        );
+        // A leading digit which would overflow the 256 bits.
+        assert!("zmdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73"
+            .parse::<NarHash>()
+            .is_err());
+    }
+}
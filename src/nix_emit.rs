@@ -0,0 +1,99 @@
+use std::borrow::Cow;
+
+use crate::flake_lock::NodeEdge;
+use crate::promote::PromotedInput;
+use crate::Redirection;
+
+/// Quote an attribute name if it is not a valid bare Nix identifier.
+pub fn attr_name(name: &str) -> Cow<'_, str> {
+    let mut chars = name.chars();
+    let is_ident = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-'));
+    if is_ident {
+        Cow::Borrowed(name)
+    } else {
+        Cow::Owned(string_literal(name))
+    }
+}
+
+/// Quote and escape a string as a Nix string literal.
+pub fn string_literal(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${");
+    format!("\"{escaped}\"")
+}
+
+/// The `inputs` declarations of a `flake.nix` which would produce the same
+/// lock as the redirections made by pruning.
+///
+/// Renders as a Nix attribute set, with one line per promoted input
+/// and one `inputs.<a>.inputs.<b>.follows = "<c>";` line per redirection.
+pub struct FollowsDeclarations<'a> {
+    promoted: &'a [PromotedInput],
+    redirections: Vec<&'a Redirection>,
+}
+
+impl<'a> FollowsDeclarations<'a> {
+    /// Redirections which reference node indices are ignored,
+    /// because they cannot be expressed in `flake.nix`, as are redirections
+    /// beneath an input which is itself redirected.
+    pub fn new(promoted: &'a [PromotedInput], redirections: &'a [Redirection]) -> Self {
+        let mut redirections = redirections
+            .iter()
+            .filter(|r| matches!(r.new, NodeEdge::Follows(_)))
+            .collect::<Vec<_>>();
+        redirections.sort_by(|a, b| a.path.cmp(&b.path));
+        redirections.dedup_by(|a, b| a.path == b.path);
+        // Once an input follows another, overriding its own inputs is meaningless.
+        let followed = redirections
+            .iter()
+            .map(|r| r.path.clone())
+            .collect::<Vec<_>>();
+        redirections.retain(|r| {
+            !followed
+                .iter()
+                .any(|path| path.len() < r.path.len() && r.path.starts_with(path))
+        });
+        Self {
+            promoted,
+            redirections,
+        }
+    }
+}
+
+impl std::fmt::Display for FollowsDeclarations<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{{")?;
+        for input in self.promoted {
+            writeln!(f, "  {input}")?;
+        }
+        for Redirection { path, new, .. } in &self.redirections {
+            let attr_path = path
+                .iter()
+                .map(|name| format!("inputs.{}", attr_name(name)))
+                .collect::<Vec<_>>()
+                .join(".");
+            let follows = string_literal(&new.to_string());
+            writeln!(f, "  {attr_path}.follows = {follows};")?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{attr_name, string_literal};
+
+    #[test]
+    fn quoting() {
+        assert_eq!(attr_name("flake-utils"), "flake-utils");
+        assert_eq!(attr_name("nixpkgs_2'"), "nixpkgs_2'");
+        assert_eq!(attr_name("1password"), "\"1password\"");
+        assert_eq!(attr_name("foo.bar"), "\"foo.bar\"");
+        assert_eq!(string_literal("a\"${b}\\"), r#""a\"\${b}\\""#);
+    }
+}
@@ -0,0 +1,303 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// A machine-readable format for command output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    Csv,
+    Toml,
+    Yaml,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "toml" => Ok(Self::Toml),
+            "yaml" => Ok(Self::Yaml),
+            _ => Err(format!(
+                "expected `json`, `csv`, `toml` or `yaml`, found `{s}`"
+            )),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Serialize `value` to `writer`, where `pretty` only affects JSON and TOML.
+    ///
+    /// To be written as CSV, `value` must be a sequence of records,
+    /// or a map, which is written with a column for the keys.
+    /// Columns are in the order that fields are first seen.
+    pub fn write(
+        self,
+        value: impl Serialize,
+        mut writer: impl Write,
+        pretty: bool,
+    ) -> Result<(), String> {
+        match self {
+            Self::Json => JsonLayout::from_pretty(pretty).write(value, writer),
+            Self::Csv => write_csv(&value, writer),
+            Self::Toml => {
+                let toml = if pretty {
+                    toml::to_string_pretty(&value)
+                } else {
+                    toml::to_string(&value)
+                };
+                let toml = toml.map_err(|e| e.to_string())?;
+                writer.write_all(toml.as_bytes()).map_err(|e| e.to_string())
+            }
+            Self::Yaml => {
+                let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+                let mut yaml = String::new();
+                write_yaml(&value, 0, &mut yaml);
+                writer.write_all(yaml.as_bytes()).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// The whitespace of a JSON document, so that a document can be written
+/// back in the same layout that it was read in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonLayout {
+    /// The whitespace for each level of nesting, or `None` if minified.
+    indent: Option<String>,
+    trailing_newline: bool,
+}
+
+impl JsonLayout {
+    /// Either minified, or indented by two spaces as `--pretty` does.
+    pub fn from_pretty(pretty: bool) -> Self {
+        Self {
+            indent: pretty.then(|| "  ".to_owned()),
+            trailing_newline: false,
+        }
+    }
+
+    /// Find the layout of `text`, which is assumed to be indented consistently,
+    /// by the whitespace of the first line which is indented.
+    pub fn detect(text: &str) -> Self {
+        let indent = text.trim().lines().skip(1).find_map(|line| {
+            let content = line.trim_start();
+            let indent = &line[..line.len() - content.len()];
+            (!content.is_empty() && !indent.is_empty()).then(|| indent.to_owned())
+        });
+        let indent = match indent {
+            None if text.trim().contains('\n') => Some(String::new()),
+            indent => indent,
+        };
+        Self {
+            indent,
+            trailing_newline: text.ends_with('\n'),
+        }
+    }
+
+    pub fn write(&self, value: impl Serialize, mut writer: impl Write) -> Result<(), String> {
+        match &self.indent {
+            Some(indent) => {
+                let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+                let mut serializer = serde_json::Serializer::with_formatter(&mut writer, formatter);
+                value.serialize(&mut serializer)
+            }
+            None => serde_json::to_writer(&mut writer, &value),
+        }
+        .map_err(|e| e.to_string())?;
+        if self.trailing_newline {
+            writer.write_all(b"\n").map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+fn write_csv(value: &impl Serialize, writer: impl Write) -> Result<(), String> {
+    let records = csv_records(serde_json::to_value(value).map_err(|e| e.to_string())?)?;
+
+    let mut columns = Vec::<String>::new();
+    for record in &records {
+        for name in record.keys() {
+            if !columns.contains(name) {
+                columns.push(name.clone());
+            }
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(&columns).map_err(|e| e.to_string())?;
+    for record in &records {
+        writer
+            .write_record(columns.iter().map(|name| match record.get(name) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(text)) => text.clone(),
+                // Nested values are kept intact as JSON.
+                Some(value) => value.to_string(),
+            }))
+            .map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())
+}
+
+/// Write `value` as YAML in block style, with each line indented by `indent` spaces.
+fn write_yaml(value: &Value, indent: usize, yaml: &mut String) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            for item in items {
+                // The item is written as if it were nested in a map,
+                // and then its first line is marked as the start of an item.
+                let start = yaml.len();
+                write_yaml(item, indent + 2, yaml);
+                yaml.replace_range(start + indent..start + indent + 2, "- ");
+            }
+        }
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let key = yaml_string(key);
+                match value {
+                    Value::Array(items) if !items.is_empty() => {}
+                    Value::Object(map) if !map.is_empty() => {}
+                    value => {
+                        yaml.push_str(&format!("{pad}{key}: {}\n", yaml_scalar(value)));
+                        continue;
+                    }
+                }
+                yaml.push_str(&format!("{pad}{key}:\n"));
+                write_yaml(value, indent + 2, yaml);
+            }
+        }
+        value => yaml.push_str(&format!("{pad}{}\n", yaml_scalar(value))),
+    }
+}
+
+/// A value which is written on one line, being a scalar or an empty collection.
+fn yaml_scalar(value: &Value) -> String {
+    match value {
+        Value::String(text) => yaml_string(text),
+        Value::Array(_) => "[]".to_owned(),
+        Value::Object(_) => "{}".to_owned(),
+        // Null, booleans and numbers are written the same as in JSON.
+        value => value.to_string(),
+    }
+}
+
+/// Write `text` unquoted if it cannot be mistaken for anything but a string,
+/// otherwise quoted as in JSON, which YAML also accepts.
+fn yaml_string(text: &str) -> String {
+    let plain = text
+        .chars()
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_' || first == '/')
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./+@".contains(c))
+        && !matches!(
+            text.to_ascii_lowercase().as_str(),
+            "true" | "false" | "null" | "yes" | "no" | "on" | "off" | "y" | "n"
+        );
+    if plain {
+        text.to_owned()
+    } else {
+        Value::from(text).to_string()
+    }
+}
+
+/// The records which `value` is written as in CSV, see [`OutputFormat::write`].
+pub fn csv_records(value: Value) -> Result<Vec<Map<String, Value>>, String> {
+    let records = match value {
+        Value::Array(records) => records,
+        Value::Object(map) => map
+            .into_iter()
+            .map(|(key, value)| {
+                let mut record = Map::from_iter([("key".to_owned(), Value::String(key))]);
+                match value {
+                    Value::Object(fields) => record.extend(fields),
+                    value => {
+                        record.insert("value".to_owned(), value);
+                    }
+                }
+                Value::Object(record)
+            })
+            .collect(),
+        _ => return Err("only sequences and maps can be written as CSV".to_owned()),
+    };
+    records
+        .into_iter()
+        .map(|record| match record {
+            Value::Object(fields) => Ok(fields),
+            _ => Err("only sequences of records can be written as CSV".to_owned()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{JsonLayout, OutputFormat};
+
+    #[test]
+    fn csv() {
+        let counts = BTreeMap::from([("nixpkgs", 2), ("root", 1)]);
+        let mut csv = Vec::new();
+        OutputFormat::Csv.write(&counts, &mut csv, false).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "key,value\nnixpkgs,2\nroot,1\n"
+        );
+
+        let records = [
+            serde_json::json!({ "node": "a", "path": ["a", "b"] }),
+            serde_json::json!({ "node": "b", "reason": "none, really" }),
+        ];
+        let mut csv = Vec::new();
+        OutputFormat::Csv.write(records, &mut csv, false).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "node,path,reason\na,\"[\"\"a\"\",\"\"b\"\"]\",\nb,,\"none, really\"\n"
+        );
+
+        assert!(OutputFormat::Csv
+            .write("scalar", Vec::new(), false)
+            .is_err());
+    }
+
+    #[test]
+    fn yaml() {
+        let value = serde_json::json!({
+            "nixpkgs": { "references": 2, "paths": [["tool", "nixpkgs"], []] },
+            "source": "github:o/nixpkgs",
+            "records": [{ "node": "a", "stale": true }, "yes", null],
+            "empty": {},
+        });
+        let mut yaml = Vec::new();
+        OutputFormat::Yaml.write(&value, &mut yaml, false).unwrap();
+        assert_eq!(
+            String::from_utf8(yaml).unwrap(),
+            "nixpkgs:\n  references: 2\n  paths:\n    - - tool\n      - nixpkgs\n    - []\n\
+             source: \"github:o/nixpkgs\"\n\
+             records:\n  - node: a\n    stale: true\n  - \"yes\"\n  - null\n\
+             empty: {}\n"
+        );
+    }
+
+    #[test]
+    fn json_layout() {
+        for text in [
+            "{\n    \"a\": [\n        1\n    ],\n    \"b\": {}\n}\n",
+            "{\n\t\"a\": [\n\t\t1\n\t],\n\t\"b\": {}\n}",
+            "{\"a\":[1],\"b\":{}}",
+        ] {
+            let value: serde_json::Value = serde_json::from_str(text).unwrap();
+            let mut json = Vec::new();
+            JsonLayout::detect(text).write(&value, &mut json).unwrap();
+            assert_eq!(String::from_utf8(json).unwrap(), text);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use serde_json::Value;
+
+use crate::flake_lock::{LockFile, NodeEdge};
+use crate::flake_ref::FlakeRef;
+use crate::lint::resolve_edge;
+use crate::nar_hash::NarHash;
+
+/// The `locked` attributes to give every node of a source.
+#[derive(Clone, Debug)]
+pub struct Pin {
+    pub rev: String,
+    pub nar_hash: String,
+    pub last_modified: Option<u64>,
+}
+
+impl Pin {
+    pub fn new(rev: String, nar_hash: String, last_modified: Option<u64>) -> Result<Self, String> {
+        if rev.len() != 40 || !rev.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!(
+                "The revision '{rev}' is not a commit hash of 40 hexadecimal digits"
+            ));
+        }
+        // Nix writes the hash as SRI, whichever encoding it is given in.
+        let nar_hash = nar_hash.parse::<NarHash>()?;
+        Ok(Self {
+            rev,
+            nar_hash: nar_hash.to_string(),
+            last_modified,
+        })
+    }
+}
+
+/// Whether `source` names the flake reference, ignoring case, along with any
+/// ref or query of the reference which `source` does not mention, so that
+/// `github:NixOS/nixpkgs` matches `github:nixos/nixpkgs/nixos-unstable`.
+pub fn source_matches(source: &str, reference: FlakeRef) -> bool {
+    let reference = reference.to_string().to_ascii_lowercase();
+    let source = source.to_ascii_lowercase();
+    match reference.strip_prefix(&source) {
+        Some(rest) => rest.is_empty() || rest.starts_with(['/', '?']),
+        None => false,
+    }
+}
+
+/// The indices of every node which was originally declared as `source`, sorted.
+pub fn matching_nodes(lock: &LockFile, source: &str) -> Vec<String> {
+    let mut indices = lock
+        .node_indices()
+        .filter(|index| {
+            lock.get_node(index)
+                .and_then(|node| {
+                    node.original()
+                        .map(|original| source_matches(source, original))
+                })
+                .unwrap_or(false)
+        })
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    indices.sort_unstable();
+    indices
+}
+
+/// Every input path which leads to one of the nodes `indices`.
+pub fn affected_paths(lock: &LockFile, indices: &[String]) -> Vec<Vec<String>> {
+    let mut paths = Vec::new();
+    lock.walk_inputs(&mut |path, _, _, target| {
+        if indices.iter().any(|index| index == target) {
+            paths.push(path.to_vec());
+        }
+    });
+    paths
+}
+
+/// Rewrite the `locked` attributes of the nodes `indices`.
+///
+/// Attributes which depend on the revision and are not given by `pin`,
+/// `lastModified` and `revCount`, are removed, for Nix to find them again.
+pub fn pin_nodes(lock: &LockFile, indices: &[String], pin: &Pin) {
+    for index in indices {
+        let mut node = lock
+            .get_node_mut(index)
+            .expect("a node to exist with this index");
+        let Some(locked) = node.locked_mut() else {
+            continue;
+        };
+        locked.insert("rev".to_owned(), Value::from(pin.rev.clone()));
+        locked.insert("narHash".to_owned(), Value::from(pin.nar_hash.clone()));
+        match pin.last_modified {
+            Some(last_modified) => {
+                locked.insert("lastModified".to_owned(), Value::from(last_modified));
+            }
+            None => {
+                locked.shift_remove("lastModified");
+            }
+        }
+        locked.shift_remove("revCount");
+    }
+}
+
+/// Point every edge which references one of the nodes `indices` at a single one
+/// of them, preferring a node which is an input of the root, and following it.
+/// Returns the index of that node and how many edges were redirected.
+pub fn redirect_to_one(lock: &LockFile, indices: &[String]) -> (String, usize) {
+    let root = lock.root().expect(crate::EXPECT_ROOT_EXIST);
+    let root_input = root.iter_edges().find_map(|(name, edge)| {
+        let target = resolve_edge(lock, &edge)?;
+        indices.contains(&target).then(|| (name.to_owned(), target))
+    });
+    drop(root);
+    let (kept, new) = match root_input {
+        Some((name, target)) => (target, NodeEdge::from_iter([name])),
+        None => (indices[0].clone(), NodeEdge::from(indices[0].as_str())),
+    };
+
+    // Follows lead to the redirected edges, so only indexed edges are changed.
+    let mut redirected = Vec::new();
+    for index in lock.node_indices() {
+        let node = lock
+            .get_node(index)
+            .expect("a node to exist with this index");
+        for (name, edge) in node.iter_edges() {
+            if let NodeEdge::Indexed(target) = &*edge {
+                if target != &kept && indices.contains(target) {
+                    redirected.push((index.to_owned(), name.to_owned()));
+                }
+            }
+        }
+    }
+    for (index, name) in &redirected {
+        let node = lock
+            .get_node(index)
+            .expect("a node to exist with this index");
+        *node.get_edge_mut(name).expect("the edge to exist") = new.clone();
+    }
+    (kept, redirected.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{affected_paths, matching_nodes, pin_nodes, redirect_to_one, source_matches, Pin};
+    use crate::flake_lock::LockFile;
+    use crate::flake_ref::FlakeRef;
+
+    #[test]
+    fn matches() {
+        let original = json!({ "type": "github", "owner": "NixOS", "repo": "nixpkgs", "ref": "nixos-unstable" });
+        let original = FlakeRef::new(original.as_object().unwrap());
+        assert!(source_matches("github:nixos/nixpkgs", original));
+        assert!(source_matches(
+            "github:NixOS/nixpkgs/nixos-unstable",
+            original
+        ));
+        assert!(!source_matches(
+            "github:NixOS/nixpkgs/nixos-24.05",
+            original
+        ));
+        assert!(!source_matches("github:NixOS/nix", original));
+    }
+
+    #[test]
+    fn pins() {
+        let lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "nixpkgs": {
+                        "locked": { "type": "github", "owner": "NixOS", "repo": "nixpkgs", "rev": "a", "lastModified": 1 },
+                        "original": { "type": "github", "owner": "NixOS", "repo": "nixpkgs" }
+                    },
+                    "nixpkgs_2": {
+                        "locked": { "type": "github", "owner": "nixos", "repo": "nixpkgs", "rev": "b" },
+                        "original": { "type": "github", "owner": "nixos", "repo": "nixpkgs" }
+                    },
+                    "tool": {
+                        "inputs": { "nixpkgs": "nixpkgs_2" },
+                        "locked": { "type": "github", "owner": "o", "repo": "tool" },
+                        "original": { "type": "github", "owner": "o", "repo": "tool" }
+                    },
+                    "root": { "inputs": { "pkgs": "nixpkgs", "tool": "tool" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let indices = matching_nodes(&lock, "github:NixOS/nixpkgs");
+        assert_eq!(indices, ["nixpkgs", "nixpkgs_2"]);
+        assert_eq!(
+            affected_paths(&lock, &indices),
+            [vec!["pkgs"], vec!["tool", "nixpkgs"]]
+        );
+
+        assert!(Pin::new("0".repeat(40), "sha256-x".to_owned(), None).is_err());
+        let pin = Pin::new("0".repeat(40), "0".repeat(64), None).unwrap();
+        assert_eq!(pin.nar_hash, format!("sha256-{}=", "A".repeat(43)));
+        pin_nodes(&lock, &indices, &pin);
+        let nixpkgs = lock.get_node("nixpkgs").unwrap();
+        assert_eq!(nixpkgs.locked().unwrap().rev(), Some(pin.rev.as_str()));
+        assert_eq!(nixpkgs.locked().unwrap().last_modified(), None);
+        drop(nixpkgs);
+
+        assert_eq!(redirect_to_one(&lock, &indices), ("nixpkgs".to_owned(), 1));
+        assert_eq!(
+            lock.follow_path(["tool", "nixpkgs"]).as_deref(),
+            Some("nixpkgs")
+        );
+    }
+}
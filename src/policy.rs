@@ -0,0 +1,293 @@
+use owo_colors::OwoColorize;
+
+use crate::elogln;
+use crate::flake_lock::{LockFile, NodeEdge};
+use crate::flake_ref::FlakeRef;
+
+/// Rules restricting where the inputs of a lock may come from.
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    /// The owners which inputs may belong to, compared ignoring case.
+    /// Any owner is allowed if this is empty, otherwise inputs whose owner
+    /// cannot be found, see [`url_owner`], are not allowed.
+    pub allowed_owners: Vec<String>,
+    pub forbid_path: bool,
+    pub require_nar_hash: bool,
+    pub forbid_indirect: bool,
+    /// Whether `tarball` and `file` inputs must be fetched over `https`.
+    pub https_tarballs: bool,
+}
+
+/// A rule of the policy which a node breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    ForeignOwner { owner: String },
+    UnknownOwner { url: String },
+    PathInput { path: String },
+    MissingNarHash,
+    IndirectInput { id: String },
+    InsecureTarball { url: String },
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ForeignOwner { owner } => write!(f, "belongs to '{owner}', which is not allowed"),
+            Self::UnknownOwner { url } => {
+                write!(f, "is fetched from '{url}', which has no owner to allow")
+            }
+            Self::PathInput { path } => write!(f, "is the local path '{path}'"),
+            Self::MissingNarHash => write!(f, "is locked without a 'narHash'"),
+            Self::IndirectInput { id } => {
+                write!(f, "is looked up in the flake registry as '{id}'")
+            }
+            Self::InsecureTarball { url } => write!(f, "is fetched from '{url}' without https"),
+        }
+    }
+}
+
+/// The violations of a node, and every input path which references it by index.
+#[derive(Clone, Debug)]
+pub struct Offense {
+    pub node: String,
+    pub paths: Vec<Vec<String>>,
+    pub violations: Vec<Violation>,
+}
+
+impl Policy {
+    /// Check the `locked` and `original` attributes of every node which is
+    /// reachable from the root, in the order that their inputs are first found.
+    pub fn check(&self, lock: &LockFile) -> Vec<Offense> {
+        let mut reached = Vec::<(String, Vec<Vec<String>>)>::new();
+        lock.walk_inputs(&mut |path, _, edge, target| {
+            // Only the inputs which declare the node are listed, not those which follow them.
+            if matches!(edge, NodeEdge::Follows(_)) {
+                return;
+            }
+            if let Some((_, paths)) = reached.iter_mut().find(|(index, _)| index == target) {
+                paths.push(path.to_vec());
+            } else {
+                reached.push((target.to_owned(), vec![path.to_vec()]));
+            }
+        });
+        reached
+            .into_iter()
+            .filter_map(|(index, paths)| {
+                let violations = self.node_violations(lock, &index);
+                (!violations.is_empty()).then_some(Offense {
+                    node: index,
+                    paths,
+                    violations,
+                })
+            })
+            .collect()
+    }
+
+    fn node_violations(&self, lock: &LockFile, index: &str) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let Some(node) = lock.get_node(index) else {
+            return violations;
+        };
+        let locked = node.locked();
+        if self.require_nar_hash && locked.is_some_and(|locked| locked.get_str("narHash").is_none())
+        {
+            violations.push(Violation::MissingNarHash);
+        }
+        for flake_ref in [node.original(), locked].into_iter().flatten() {
+            for violation in self.ref_violations(flake_ref) {
+                if !violations.contains(&violation) {
+                    violations.push(violation);
+                }
+            }
+        }
+        violations
+    }
+
+    fn ref_violations(&self, flake_ref: FlakeRef) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if !self.allowed_owners.is_empty() {
+            let owner = match flake_ref.kind() {
+                Some("github" | "gitlab" | "sourcehut") => flake_ref.owner(),
+                Some("git" | "mercurial" | "tarball" | "file") => {
+                    let url = flake_ref.url().unwrap_or_default();
+                    let owner = url_owner(url);
+                    if owner.is_none() {
+                        violations.push(Violation::UnknownOwner {
+                            url: url.to_owned(),
+                        });
+                    }
+                    owner
+                }
+                _ => None,
+            };
+            if let Some(owner) = owner {
+                if !self
+                    .allowed_owners
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(owner))
+                {
+                    violations.push(Violation::ForeignOwner {
+                        owner: owner.to_owned(),
+                    });
+                }
+            }
+        }
+        match flake_ref.kind() {
+            Some("path") if self.forbid_path => violations.push(Violation::PathInput {
+                path: flake_ref.path().unwrap_or_default().to_owned(),
+            }),
+            Some("indirect") if self.forbid_indirect => violations.push(Violation::IndirectInput {
+                id: flake_ref.id().unwrap_or_default().to_owned(),
+            }),
+            Some("tarball" | "file") if self.https_tarballs => {
+                let url = flake_ref.url().unwrap_or_default();
+                if !url.starts_with("https://") {
+                    violations.push(Violation::InsecureTarball {
+                        url: url.to_owned(),
+                    });
+                }
+            }
+            _ => {}
+        }
+        violations
+    }
+}
+
+/// The owner of the repository or archive at `url`, being the first segment
+/// of its path, such as `NixOS` of `git+https://github.com/NixOS/nixpkgs`.
+/// There is none if the URL has no host, or nothing beneath that segment.
+fn url_owner(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    let (authority, path) = rest.split_once('/')?;
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    if host.is_empty() {
+        return None;
+    }
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+    let owner = segments.next()?;
+    segments.next()?;
+    Some(owner)
+}
+
+pub fn elog_offenses(offenses: &[Offense]) {
+    for offense in offenses {
+        let paths = offense
+            .paths
+            .iter()
+            .map(|path| format!("'{}'", path.join("/")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        for violation in &offense.violations {
+            elogln!(@quiet :bold :bright_red "policy:", :yellow (paths), (violation), :dimmed .("(node '{}')", offense.node));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{url_owner, Policy, Violation};
+    use crate::flake_lock::LockFile;
+
+    #[test]
+    fn violations() {
+        let lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "fork": {
+                        "locked": { "type": "github", "owner": "someone", "repo": "nixpkgs", "narHash": "sha256-a" },
+                        "original": { "type": "github", "owner": "someone", "repo": "nixpkgs" }
+                    },
+                    "mirror": {
+                        "locked": { "type": "git", "url": "https://github.com/someone/nixpkgs", "narHash": "sha256-d" },
+                        "original": { "type": "git", "url": "https://github.com/someone/nixpkgs" }
+                    },
+                    "nixpkgs": {
+                        "locked": { "type": "github", "owner": "NixOS", "repo": "nixpkgs", "narHash": "sha256-b" },
+                        "original": { "type": "indirect", "id": "nixpkgs" }
+                    },
+                    "src": {
+                        "locked": { "type": "tarball", "url": "http://example.com/src.tar.gz" },
+                        "original": { "type": "tarball", "url": "http://example.com/src.tar.gz" }
+                    },
+                    "tool": {
+                        "inputs": { "nixpkgs": "fork" },
+                        "locked": { "type": "path", "path": "/tool", "narHash": "sha256-c" },
+                        "original": { "type": "path", "path": "/tool" }
+                    },
+                    "root": { "inputs": { "mirror": "mirror", "nixpkgs": "nixpkgs", "src": "src", "tool": "tool" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        assert!(Policy::default().check(&lock).is_empty());
+
+        let policy = Policy {
+            allowed_owners: vec!["nixos".to_owned()],
+            forbid_path: true,
+            require_nar_hash: true,
+            forbid_indirect: true,
+            https_tarballs: true,
+        };
+        let offenses = policy
+            .check(&lock)
+            .into_iter()
+            .map(|offense| (offense.node, offense.paths, offense.violations))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            offenses,
+            [
+                (
+                    "mirror".to_owned(),
+                    vec![vec!["mirror".to_owned()]],
+                    vec![Violation::ForeignOwner {
+                        owner: "someone".to_owned()
+                    }]
+                ),
+                (
+                    "nixpkgs".to_owned(),
+                    vec![vec!["nixpkgs".to_owned()]],
+                    vec![Violation::IndirectInput {
+                        id: "nixpkgs".to_owned()
+                    }]
+                ),
+                (
+                    "src".to_owned(),
+                    vec![vec!["src".to_owned()]],
+                    vec![
+                        Violation::MissingNarHash,
+                        Violation::UnknownOwner {
+                            url: "http://example.com/src.tar.gz".to_owned()
+                        },
+                        Violation::InsecureTarball {
+                            url: "http://example.com/src.tar.gz".to_owned()
+                        }
+                    ]
+                ),
+                (
+                    "tool".to_owned(),
+                    vec![vec!["tool".to_owned()]],
+                    vec![Violation::PathInput {
+                        path: "/tool".to_owned()
+                    }]
+                ),
+                (
+                    "fork".to_owned(),
+                    vec![vec!["tool".to_owned(), "nixpkgs".to_owned()]],
+                    vec![Violation::ForeignOwner {
+                        owner: "someone".to_owned()
+                    }]
+                ),
+            ]
+        );
+
+        assert_eq!(
+            url_owner("ssh://git@gitlab.com/group/project.git?ref=main"),
+            Some("group")
+        );
+        assert_eq!(url_owner("file:///home/me/src.tar.gz"), None);
+        assert_eq!(url_owner("https://example.com/src.tar.gz"), None);
+    }
+}
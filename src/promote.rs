@@ -0,0 +1,297 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use owo_colors::OwoColorize;
+
+use crate::flake_lock::{LockFile, NodeEdge};
+use crate::nix_emit::{attr_name, string_literal};
+use crate::{elogln, Redirection, SkippedEdge, EXPECT_ROOT_EXIST};
+
+/// An input of the root node which was synthesized by [`promote_shared_inputs`].
+#[derive(Clone, Debug)]
+pub struct PromotedInput {
+    pub name: String,
+    pub url: String,
+    pub flake: bool,
+}
+
+/// An edge somewhere beneath the root, which resolves to a node with a given source.
+struct SourceEdge {
+    /// The input names taken from the root to reach this edge.
+    path: Vec<String>,
+    parent: String,
+    name: String,
+    target: String,
+}
+
+/// Find every source (by the `original` reference of its node) which is depended
+/// upon beneath two or more of the root node's inputs, and redirect each of those
+/// edges to a single node by way of an input on the root.
+///
+/// If the root already has an input with the same source, edges are redirected
+/// to that. Otherwise, a new root input is created, named after the most common
+/// input name used for the source, pointing at the most recently modified copy.
+///
+/// When `indexed == false`, redirected edges follow the root input by name.
+/// Otherwise, they reference the node index of the chosen node directly.
+/// The edges at the input paths `exclude` are left as they are.
+///
+/// Returns the inputs which were added to the root, so that the user can declare
+/// them in their `flake.nix`, along with every edge which was redirected.
+pub fn promote_shared_inputs(
+    lock: &LockFile,
+    indexed: bool,
+    exclude: &[Vec<String>],
+) -> (Vec<PromotedInput>, Vec<Redirection>, Vec<SkippedEdge>) {
+    elogln!(:bold :bright_magenta "Promoting shared transitive inputs to root inputs.");
+
+    let mut root_sources = HashMap::new();
+    let mut shared_sources = BTreeMap::<String, Vec<SourceEdge>>::new();
+    let mut skipped = Vec::new();
+    // An excluded edge is left as it is, by whichever path it is reached.
+    let mut excluded_edges = BTreeSet::new();
+    lock.walk_inputs(&mut |path, parent, edge, target| {
+        if exclude.iter().any(|excluded| excluded == path) {
+            let display_path = path.join("/");
+            elogln!(@verbose :bold (:cyan "Excluded", :yellow "'{display_path}'"), :dimmed "by the configuration");
+            excluded_edges.insert((parent.to_owned(), path[path.len() - 1].clone()));
+            skipped.push(SkippedEdge {
+                node: parent.to_owned(),
+                path: path.to_vec(),
+                edge: edge.clone(),
+                reason: "it is excluded by the configuration".to_owned(),
+            });
+            return;
+        }
+        let Some(source) = lock
+            .get_node(target)
+            .and_then(|node| node.original().map(|original| original.to_string()))
+        else {
+            return;
+        };
+        if let [name] = path {
+            root_sources.entry(source).or_insert_with(|| name.clone());
+        } else if let NodeEdge::Indexed(_) = edge {
+            shared_sources.entry(source).or_default().push(SourceEdge {
+                path: path.to_vec(),
+                parent: parent.to_owned(),
+                name: path.last().unwrap().clone(),
+                target: target.to_owned(),
+            });
+        }
+    });
+
+    let mut promoted = Vec::new();
+    let mut redirections = Vec::new();
+    for (source, mut edges) in shared_sources {
+        edges.retain(|e| !excluded_edges.contains(&(e.parent.clone(), e.name.clone())));
+        let subtrees = edges.iter().map(|e| &e.path[0]).collect::<BTreeSet<_>>();
+        if subtrees.len() < 2 {
+            continue;
+        }
+
+        let (root_name, chosen) = if let Some(name) = root_sources.get(&source) {
+            let chosen = lock.follow_path([name]).expect("the root input to resolve");
+            (name.clone(), chosen)
+        } else {
+            let name = most_common_name(&edges);
+            let root = lock.root().expect(EXPECT_ROOT_EXIST);
+            if let Some(edge) = root.get_edge(&name) {
+                elogln!(@verbose
+                    :bold (:cyan "Cannot promote", :yellow "'{source}'"),
+                    "because the root already has an input named", :yellow "'{name}'",
+                    :dimmed "(" :dimmed :italic "'{edge}'" :dimmed ")"
+                );
+                skipped.extend(edges.into_iter().map(|e| SkippedEdge {
+                    node: e.parent,
+                    path: e.path,
+                    edge: NodeEdge::from(e.target),
+                    reason: format!("the root already has an input named '{name}'"),
+                }));
+                continue;
+            }
+            drop(root);
+            let chosen = newest_node(lock, &edges);
+            let node = lock
+                .get_node(&chosen)
+                .expect("a node to exist with this index");
+            promoted.push(PromotedInput {
+                name: name.clone(),
+                url: source.clone(),
+                flake: node.is_flake(),
+            });
+            drop(node);
+            lock.get_node_mut(lock.root_index())
+                .expect(EXPECT_ROOT_EXIST)
+                .insert_edge(&name, NodeEdge::from(chosen.as_str()));
+            elogln!(:bold (:bright_cyan "Promoted", :green "'{source}'", :bright_cyan "to root input", :green "'{name}'"), :dimmed "(" :dimmed :italic "'{chosen}'" :dimmed ")");
+            (name, chosen)
+        };
+
+        // A node may be reachable by several paths, prefer the shortest.
+        edges.sort_by_key(|e| e.path.len());
+        let mut redirected = BTreeSet::new();
+        for SourceEdge {
+            path,
+            parent,
+            name,
+            target,
+        } in edges
+        {
+            if !redirected.insert((parent.clone(), name.clone())) || (indexed && target == chosen) {
+                continue;
+            }
+            let node = lock
+                .get_node(&parent)
+                .expect("a node to exist with this index");
+            let mut edge = node.get_edge_mut(&name).expect("the edge to exist");
+            let display_path = path.join("/");
+            let old = if indexed {
+                let old = std::mem::replace(&mut *edge, NodeEdge::from(chosen.as_str()));
+                elogln!(@verbose "-", :yellow "'{display_path}'", "now references", :italic :purple "'{edge}'", :dimmed "(was '{old}')");
+                old
+            } else {
+                let old = std::mem::replace(&mut *edge, NodeEdge::from_iter([&root_name]));
+                elogln!(@verbose "-", :yellow "'{display_path}'", "now follows", :green "'{edge}'", :dimmed "(was '{old}')");
+                old
+            };
+            let new = edge.clone();
+            drop(edge);
+            drop(node);
+            redirections.push(Redirection {
+                node: parent,
+                path,
+                old,
+                new,
+            });
+        }
+    }
+
+    (promoted, redirections, skipped)
+}
+
+/// Print the declarations which should be added to the `inputs` of `flake.nix`
+/// so that the promoted inputs are kept when the lock is next updated.
+pub fn elog_promoted_inputs(promoted: &[PromotedInput]) {
+    if promoted.is_empty() {
+        return;
+    }
+    elogln!(:bold :bright_magenta "Declare the promoted inputs in your `flake.nix`:");
+    for input in promoted {
+        elogln!("  "(input));
+    }
+}
+
+/// Renders the declaration of the input as it would appear in `flake.nix`.
+impl std::fmt::Display for PromotedInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { name, url, flake } = self;
+        let (name, url) = (attr_name(name), string_literal(url));
+        if *flake {
+            write!(f, "inputs.{name}.url = {url};")
+        } else {
+            write!(f, "inputs.{name} = {{ url = {url}; flake = false; }};")
+        }
+    }
+}
+
+fn most_common_name(edges: &[SourceEdge]) -> String {
+    let mut counts = BTreeMap::<&str, usize>::new();
+    for edge in edges {
+        *counts.entry(&edge.name).or_default() += 1;
+    }
+    // Ties are broken by the alphabetically first name.
+    let (name, _) = counts
+        .into_iter()
+        .rev()
+        .max_by_key(|&(_, count)| count)
+        .expect("at least one edge");
+    name.to_owned()
+}
+
+fn newest_node(lock: &LockFile, edges: &[SourceEdge]) -> String {
+    let targets = edges.iter().map(|e| &e.target).collect::<BTreeSet<_>>();
+    let last_modified = |index: &str| {
+        lock.get_node(index)
+            .and_then(|node| node.locked().and_then(|locked| locked.last_modified()))
+            .unwrap_or_default()
+    };
+    // Ties are broken by the alphabetically first index.
+    targets
+        .into_iter()
+        .rev()
+        .max_by_key(|index| last_modified(index))
+        .expect("at least one edge")
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::promote_shared_inputs;
+    use crate::flake_lock::{LockFile, NodeEdge};
+
+    const LOCK: &str = r#"{
+        "nodes": {
+            "a": {
+                "inputs": { "utils": "utils" },
+                "locked": { "type": "github", "owner": "o", "repo": "a" },
+                "original": { "type": "github", "owner": "o", "repo": "a" }
+            },
+            "b": {
+                "inputs": { "utils": "utils_2" },
+                "locked": { "type": "github", "owner": "o", "repo": "b" },
+                "original": { "type": "github", "owner": "o", "repo": "b" }
+            },
+            "c": {
+                "inputs": { "utils": "utils_3" },
+                "locked": { "type": "github", "owner": "o", "repo": "c" },
+                "original": { "type": "github", "owner": "o", "repo": "c" }
+            },
+            "utils": {
+                "locked": { "type": "github", "owner": "o", "repo": "utils" },
+                "original": { "type": "github", "owner": "o", "repo": "utils" }
+            },
+            "utils_2": {
+                "locked": { "type": "github", "owner": "o", "repo": "utils" },
+                "original": { "type": "github", "owner": "o", "repo": "utils" }
+            },
+            "utils_3": {
+                "locked": { "type": "github", "owner": "o", "repo": "utils" },
+                "original": { "type": "github", "owner": "o", "repo": "utils" }
+            },
+            "root": { "inputs": { "a": "a", "b": "b", "c": "c" } }
+        },
+        "root": "root",
+        "version": 7
+    }"#;
+
+    fn edge(lock: &LockFile, parent: &str) -> NodeEdge {
+        lock.get_node(parent)
+            .unwrap()
+            .get_edge("utils")
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn promoted() {
+        let lock: LockFile = serde_json::from_str(LOCK).unwrap();
+        let (promoted, redirections, skipped) = promote_shared_inputs(&lock, false, &[]);
+        assert_eq!(promoted.len(), 1);
+        assert_eq!(redirections.len(), 3);
+        assert!(skipped.is_empty());
+        assert_eq!(edge(&lock, "a"), NodeEdge::from_iter(["utils"]));
+    }
+
+    #[test]
+    fn excluded() {
+        let lock: LockFile = serde_json::from_str(LOCK).unwrap();
+        let exclude = [vec!["a".to_owned(), "utils".to_owned()]];
+        let (promoted, redirections, skipped) = promote_shared_inputs(&lock, false, &exclude);
+        assert_eq!(promoted.len(), 1);
+        assert_eq!(redirections.len(), 2);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].path, exclude[0]);
+        assert_eq!(edge(&lock, "a"), NodeEdge::from("utils"));
+        assert_eq!(edge(&lock, "b"), NodeEdge::from_iter(["utils"]));
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::flake_lock::{LockFile, NodeEdge};
+use crate::lint::{lint_lock, resolve_edge, Problem};
+use crate::EXPECT_ROOT_EXIST;
+
+/// An edge which did not resolve, and was pointed at a node
+/// with the same input name elsewhere in the lock.
+#[derive(Clone, Debug)]
+pub struct RepairedEdge {
+    /// Index of the node which owns the edge.
+    pub node: String,
+    pub input: String,
+    pub old: NodeEdge,
+    pub new: NodeEdge,
+}
+
+/// Redirect every edge which does not resolve, if there is an unambiguous
+/// replacement, returning the repairs and the problems which could not be fixed.
+///
+/// The candidates for an edge are the nodes which other edges of the same
+/// input name resolve to, and they must all have the same source.
+/// If the root has an input of that name, the edge follows it,
+/// otherwise there must be only one candidate, which is referenced by index.
+pub fn repair_dangling_edges(lock: &LockFile) -> (Vec<RepairedEdge>, Vec<Problem>) {
+    let mut repaired = Vec::new();
+    if lock.root().is_none() {
+        return (repaired, lint_lock(lock));
+    }
+    // Repairs may allow follows through the repaired edges to resolve,
+    // so indices are repaired before follows, and problems are found again.
+    loop {
+        let candidates = candidates_by_input_name(lock);
+        let problems = lint_lock(lock);
+        let mut progressed = false;
+        for follows in [false, true] {
+            for problem in &problems {
+                let (node, input) = match problem {
+                    Problem::DanglingIndex { node, input, .. } if !follows => (node, input),
+                    Problem::UnresolvedFollows { node, input, .. } if follows => (node, input),
+                    _ => continue,
+                };
+                let Some(new) = candidates
+                    .get(input)
+                    .and_then(|candidates| replacement(lock, candidates, input))
+                else {
+                    continue;
+                };
+                let owner = lock
+                    .get_node(node)
+                    .expect("a node to exist with this index");
+                let mut edge = owner.get_edge_mut(input).expect("the edge to exist");
+                let old = std::mem::replace(&mut *edge, new.clone());
+                repaired.push(RepairedEdge {
+                    node: node.clone(),
+                    input: input.clone(),
+                    old,
+                    new,
+                });
+                progressed = true;
+            }
+            if progressed {
+                break;
+            }
+        }
+        if !progressed {
+            break;
+        }
+    }
+
+    let unfixed = lint_lock(lock)
+        .into_iter()
+        .filter(|problem| {
+            matches!(
+                problem,
+                Problem::DanglingIndex { .. } | Problem::UnresolvedFollows { .. }
+            )
+        })
+        .collect();
+    (repaired, unfixed)
+}
+
+/// The nodes which edges of each input name resolve to.
+fn candidates_by_input_name(lock: &LockFile) -> BTreeMap<String, BTreeSet<String>> {
+    let mut candidates = BTreeMap::<String, BTreeSet<String>>::new();
+    for index in lock.node_indices() {
+        let node = lock
+            .get_node(index)
+            .expect("a node to exist with this index");
+        for (name, edge) in node.iter_edges() {
+            if let Some(target) = resolve_edge(lock, &edge) {
+                candidates
+                    .entry(name.to_owned())
+                    .or_default()
+                    .insert(target);
+            }
+        }
+    }
+    candidates
+}
+
+fn replacement(lock: &LockFile, candidates: &BTreeSet<String>, input: &str) -> Option<NodeEdge> {
+    let sources = candidates
+        .iter()
+        .map(|index| {
+            lock.get_node(index)
+                .and_then(|node| node.original().map(|original| original.to_string()))
+        })
+        .collect::<BTreeSet<_>>();
+    if sources.len() != 1 {
+        return None;
+    }
+
+    let root = lock.root().expect(EXPECT_ROOT_EXIST);
+    let root_target = root
+        .get_edge(input)
+        .and_then(|edge| resolve_edge(lock, &edge));
+    match root_target {
+        Some(target) if candidates.contains(&target) => Some(NodeEdge::from_iter([input])),
+        _ if candidates.len() == 1 => candidates
+            .first()
+            .map(|index| NodeEdge::from(index.as_str())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::repair_dangling_edges;
+    use crate::flake_lock::{LockFile, NodeEdge};
+
+    #[test]
+    fn repairs() {
+        let lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "a": {
+                        "inputs": { "b": "b_gone", "c": ["a", "b", "c"], "d": "d_gone" },
+                        "locked": { "type": "github", "owner": "o", "repo": "a" },
+                        "original": { "type": "github", "owner": "o", "repo": "a" }
+                    },
+                    "b": {
+                        "inputs": { "c": "c" },
+                        "locked": { "type": "github", "owner": "o", "repo": "b" },
+                        "original": { "type": "github", "owner": "o", "repo": "b" }
+                    },
+                    "c": {
+                        "locked": { "type": "github", "owner": "o", "repo": "c" },
+                        "original": { "type": "github", "owner": "o", "repo": "c" }
+                    },
+                    "root": { "inputs": { "a": "a", "b": "b" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let (repaired, unfixed) = repair_dangling_edges(&lock);
+        let repaired = repaired
+            .iter()
+            .map(|r| (r.node.as_str(), r.input.as_str(), r.new.clone()))
+            .collect::<Vec<_>>();
+        // The follows resolves once the index it goes through is repaired.
+        assert_eq!(repaired, [("a", "b", NodeEdge::from_iter(["b"]))]);
+        assert_eq!(unfixed.len(), 1);
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::flake_lock::NodeEdge;
+use crate::PruneOutcome;
+
+/// A summary of everything `prune` changed, for consumption by other tools.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneReport<'a> {
+    redirected: Vec<RedirectedEdge<'a>>,
+    skipped: Vec<SkippedEdge<'a>>,
+    removed: Vec<RemovedNode<'a>>,
+    counts: Counts,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RedirectedEdge<'a> {
+    node: &'a str,
+    input: &'a str,
+    path: &'a [String],
+    old: &'a NodeEdge,
+    new: &'a NodeEdge,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SkippedEdge<'a> {
+    node: &'a str,
+    input: &'a str,
+    path: &'a [String],
+    edge: &'a NodeEdge,
+    reason: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RemovedNode<'a> {
+    node: &'a str,
+    /// The flake reference of the node, as it was originally declared.
+    source: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Counts {
+    nodes_before: usize,
+    nodes_after: usize,
+    /// How many times each node is referenced, by index.
+    references_before: BTreeMap<String, u32>,
+    references_after: BTreeMap<String, u32>,
+}
+
+impl<'a> PruneReport<'a> {
+    /// Describe `outcome`, given how many times each node was referenced
+    /// before and after pruning.
+    pub fn new(
+        outcome: &'a PruneOutcome,
+        references_before: BTreeMap<String, u32>,
+        references_after: BTreeMap<String, u32>,
+    ) -> Self {
+        let redirected = outcome
+            .redirections
+            .iter()
+            .map(|r| RedirectedEdge {
+                node: &r.node,
+                input: last_name(&r.path),
+                path: &r.path,
+                old: &r.old,
+                new: &r.new,
+            })
+            .collect();
+        let skipped = outcome
+            .skipped
+            .iter()
+            .map(|s| SkippedEdge {
+                node: &s.node,
+                input: last_name(&s.path),
+                path: &s.path,
+                edge: &s.edge,
+                reason: &s.reason,
+            })
+            .collect();
+        let removed = outcome
+            .removed
+            .iter()
+            .map(|(index, node)| RemovedNode {
+                node: index,
+                source: node.original().map(|original| original.to_string()),
+            })
+            .collect();
+        Self {
+            redirected,
+            skipped,
+            removed,
+            counts: Counts {
+                nodes_before: references_before.len(),
+                nodes_after: references_after.len(),
+                references_before,
+                references_after,
+            },
+        }
+    }
+}
+
+/// A single change of a [`PruneReport`], for formats which are tabular.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Change<'a> {
+    change: &'static str,
+    node: &'a str,
+    path: String,
+    old: Option<&'a NodeEdge>,
+    new: Option<&'a NodeEdge>,
+    reason: Option<&'a str>,
+    source: Option<&'a str>,
+}
+
+impl PruneReport<'_> {
+    /// Every redirected edge, skipped edge and removed node as a flat record,
+    /// leaving out the counts.
+    pub fn changes(&self) -> Vec<Change<'_>> {
+        let redirected = self.redirected.iter().map(|r| Change {
+            change: "redirected",
+            node: r.node,
+            path: r.path.join("/"),
+            old: Some(r.old),
+            new: Some(r.new),
+            reason: None,
+            source: None,
+        });
+        let skipped = self.skipped.iter().map(|s| Change {
+            change: "skipped",
+            node: s.node,
+            path: s.path.join("/"),
+            old: Some(s.edge),
+            new: None,
+            reason: Some(s.reason),
+            source: None,
+        });
+        let removed = self.removed.iter().map(|r| Change {
+            change: "removed",
+            node: r.node,
+            path: String::new(),
+            old: None,
+            new: None,
+            reason: None,
+            source: r.source.as_deref(),
+        });
+        redirected.chain(skipped).chain(removed).collect()
+    }
+}
+
+fn last_name(path: &[String]) -> &str {
+    path.last().map_or("", String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::PruneReport;
+    use crate::flake_lock::LockFile;
+    use crate::prune_lock;
+
+    #[test]
+    fn pruned_twice() {
+        let mut lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "nixpkgs": {
+                        "locked": { "type": "github", "owner": "o", "repo": "nixpkgs" },
+                        "original": { "type": "github", "owner": "o", "repo": "nixpkgs" }
+                    },
+                    "nixpkgs_2": {
+                        "locked": { "type": "github", "owner": "o", "repo": "nixpkgs" },
+                        "original": { "type": "github", "owner": "o", "repo": "nixpkgs" }
+                    },
+                    "tool": {
+                        "inputs": { "nixpkgs": "nixpkgs_2" },
+                        "locked": { "type": "github", "owner": "o", "repo": "tool" },
+                        "original": { "type": "github", "owner": "o", "repo": "tool" }
+                    },
+                    "root": { "inputs": { "nixpkgs": "nixpkgs", "tool": "tool" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let outcome = prune_lock(&mut lock, false, false, &[]);
+        let report = PruneReport::new(&outcome, BTreeMap::new(), BTreeMap::new());
+        assert_eq!((report.redirected.len(), report.removed.len()), (1, 1));
+
+        // Edges which already follow the root are not reported again.
+        let outcome = prune_lock(&mut lock, false, false, &[]);
+        let report = PruneReport::new(&outcome, BTreeMap::new(), BTreeMap::new());
+        assert!(report.redirected.is_empty());
+        assert!(report.removed.is_empty());
+    }
+}
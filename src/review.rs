@@ -0,0 +1,349 @@
+use std::io::{self, Write};
+use std::panic;
+use std::sync::Arc;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::{cursor, execute, queue, style, terminal};
+use owo_colors::OwoColorize;
+
+use crate::flake_lock::{LockFile, NodeEdge};
+use crate::fmt_colors::capture;
+use crate::{prune_lock, substitute_flake_inputs_with_follows, Redirection};
+
+/// A redirection which `prune` would make, and whether it should be made.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub redirection: Redirection,
+    pub accepted: bool,
+}
+
+/// The redirections of a lock being accepted or rejected one by one.
+#[derive(Clone, Debug)]
+pub struct Review<'a> {
+    lock: &'a LockFile,
+    indexed: bool,
+    candidates: Vec<Candidate>,
+    /// Exclusions from the configuration which no longer match a redirection.
+    other_exclusions: Vec<Vec<String>>,
+}
+
+impl<'a> Review<'a> {
+    /// Find every redirection of the lock, rejecting those which are `excluded`.
+    pub fn new(lock: &'a LockFile, indexed: bool, excluded: &[Vec<String>]) -> Self {
+        let scratch = lock.clone();
+        let ((redirections, _), _) =
+            capture(|| substitute_flake_inputs_with_follows(&scratch, indexed, &[]));
+        // Edges which would be left as they are have nothing to review.
+        let candidates = redirections
+            .into_iter()
+            .filter(|redirection| redirection.old != redirection.new)
+            .map(|redirection| Candidate {
+                accepted: !excluded.contains(&redirection.path),
+                redirection,
+            })
+            .collect::<Vec<_>>();
+        let other_exclusions = excluded
+            .iter()
+            .filter(|path| {
+                !candidates
+                    .iter()
+                    .any(|candidate| &candidate.redirection.path == *path)
+            })
+            .cloned()
+            .collect();
+        Self {
+            lock,
+            indexed,
+            candidates,
+            other_exclusions,
+        }
+    }
+
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    pub fn toggle(&mut self, i: usize) {
+        if let Some(candidate) = self.candidates.get_mut(i) {
+            candidate.accepted = !candidate.accepted;
+        }
+    }
+
+    pub fn set_all(&mut self, accepted: bool) {
+        for candidate in &mut self.candidates {
+            candidate.accepted = accepted;
+        }
+    }
+
+    /// The input paths to exclude, being those of every rejected redirection.
+    pub fn exclusions(&self) -> Vec<Vec<String>> {
+        let mut exclusions = self.other_exclusions.clone();
+        exclusions.extend(
+            self.candidates
+                .iter()
+                .filter(|candidate| !candidate.accepted)
+                .map(|candidate| candidate.redirection.path.clone()),
+        );
+        exclusions.sort_unstable();
+        exclusions
+    }
+
+    /// Prune a copy of the lock with only the accepted redirections,
+    /// returning it and the indices of the nodes which were removed.
+    pub fn outcome(&self) -> (LockFile, Vec<String>) {
+        let mut lock = self.lock.clone();
+        let (outcome, _) =
+            capture(|| prune_lock(&mut lock, self.indexed, false, &self.exclusions()));
+        let removed = outcome
+            .removed
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect();
+        (lock, removed)
+    }
+}
+
+/// Step through the redirections in the terminal, returning whether to save the choices.
+pub fn run(review: &mut Review, title: &str) -> io::Result<bool> {
+    // Panics abort in release builds, so the guard would not be dropped,
+    // and the terminal is restored before the panic is reported instead.
+    let previous_hook = Arc::new(panic::take_hook());
+    panic::set_hook(Box::new({
+        let previous_hook = Arc::clone(&previous_hook);
+        move |info| {
+            let _ = restore_terminal();
+            previous_hook(info);
+        }
+    }));
+
+    let result = (|| {
+        let mut stderr = io::stderr();
+        terminal::enable_raw_mode()?;
+        let _guard = TerminalGuard;
+        execute!(stderr, terminal::EnterAlternateScreen, cursor::Hide)?;
+        event_loop(&mut stderr, review, title)
+    })();
+
+    drop(panic::take_hook());
+    panic::set_hook(Box::new(move |info| previous_hook(info)));
+    result
+}
+
+/// Restores the terminal when dropped, whether or not the review succeeded.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = restore_terminal();
+    }
+}
+
+fn restore_terminal() -> io::Result<()> {
+    execute!(io::stderr(), cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()
+}
+
+fn event_loop(writer: &mut impl Write, review: &mut Review, title: &str) -> io::Result<bool> {
+    let mut selected = 0;
+    let mut removed = review.outcome().1;
+    loop {
+        draw(writer, review, title, selected, &removed)?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let last = review.candidates().len().saturating_sub(1);
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => selected = selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => selected = (selected + 1).min(last),
+            KeyCode::Home | KeyCode::Char('g') => selected = 0,
+            KeyCode::End | KeyCode::Char('G') => selected = last,
+            KeyCode::Char(' ') | KeyCode::Enter => review.toggle(selected),
+            KeyCode::Char('a') => review.set_all(true),
+            KeyCode::Char('n') => review.set_all(false),
+            KeyCode::Char('s') => return Ok(true),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(false)
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            _ => continue,
+        }
+        removed = review.outcome().1;
+    }
+}
+
+fn draw(
+    writer: &mut impl Write,
+    review: &Review,
+    title: &str,
+    selected: usize,
+    removed: &[String],
+) -> io::Result<()> {
+    let (width, height) = terminal::size()?;
+    let (width, height) = (usize::from(width), usize::from(height));
+    queue!(
+        writer,
+        terminal::Clear(terminal::ClearType::All),
+        cursor::MoveTo(0, 0)
+    )?;
+    let mut lines = vec![
+        format!("{} {}", "Reviewing redirections of".bold(), title.green()),
+        format!(
+            "{}",
+            "↑/↓ move  space toggle  a accept all  n reject all  s save  q quit".dimmed()
+        ),
+        String::new(),
+    ];
+
+    let removed_text = if removed.is_empty() {
+        "none".to_owned()
+    } else {
+        removed.join(", ")
+    };
+    let footer = [
+        String::new(),
+        format!(
+            "{} {}",
+            format!("Nodes removed ({}):", removed.len()).bold(),
+            truncate(&removed_text, width.saturating_sub(20))
+        ),
+    ];
+
+    // Only as many candidates as fit are shown, keeping the selected one in view.
+    let room = height.saturating_sub(lines.len() + footer.len()).max(1);
+    let candidates = review.candidates();
+    let start = selected
+        .saturating_sub(room - 1)
+        .min(candidates.len().saturating_sub(room));
+    let path_width = candidates
+        .iter()
+        .map(|candidate| candidate.redirection.path.join("/").len())
+        .max()
+        .unwrap_or(0);
+    for (i, candidate) in candidates.iter().enumerate().skip(start).take(room) {
+        let Redirection { path, old, new, .. } = &candidate.redirection;
+        let check = if candidate.accepted {
+            format!("{}", "[x]".bright_green())
+        } else {
+            format!("{}", "[ ]".red())
+        };
+        let change = match new {
+            NodeEdge::Follows(_) => format!("{old} → follows '{new}'"),
+            NodeEdge::Indexed(_) => format!("{old} → references '{new}'"),
+        };
+        let path = format!("{:path_width$}", path.join("/"));
+        let line = if i == selected {
+            format!(
+                "{} {check} {} {}",
+                ">".bold(),
+                path.reversed(),
+                change.dimmed()
+            )
+        } else {
+            format!("  {check} {} {}", path.yellow(), change.dimmed())
+        };
+        lines.push(line);
+    }
+    lines.extend(footer);
+
+    for line in lines.iter().take(height) {
+        queue!(writer, style::Print(line), style::Print("\r\n"))?;
+    }
+    writer.flush()
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_owned();
+    }
+    let mut text = text
+        .chars()
+        .take(width.saturating_sub(1))
+        .collect::<String>();
+    text.push('…');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Review;
+    use crate::flake_lock::LockFile;
+
+    #[test]
+    fn choices() {
+        let lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "nixpkgs": {
+                        "locked": { "type": "github", "owner": "o", "repo": "nixpkgs" },
+                        "original": { "type": "github", "owner": "o", "repo": "nixpkgs" }
+                    },
+                    "nixpkgs_2": {
+                        "locked": { "type": "github", "owner": "o", "repo": "nixpkgs" },
+                        "original": { "type": "github", "owner": "o", "repo": "nixpkgs" }
+                    },
+                    "systems": {
+                        "locked": { "type": "github", "owner": "o", "repo": "systems" },
+                        "original": { "type": "github", "owner": "o", "repo": "systems" }
+                    },
+                    "systems_2": {
+                        "locked": { "type": "github", "owner": "o", "repo": "systems" },
+                        "original": { "type": "github", "owner": "o", "repo": "systems" }
+                    },
+                    "tool": {
+                        "inputs": { "nixpkgs": "nixpkgs_2", "systems": "systems_2" },
+                        "locked": { "type": "github", "owner": "o", "repo": "tool" },
+                        "original": { "type": "github", "owner": "o", "repo": "tool" }
+                    },
+                    "root": { "inputs": { "nixpkgs": "nixpkgs", "systems": "systems", "tool": "tool" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let stale = vec!["gone".to_owned(), "input".to_owned()];
+        let tool_nixpkgs = vec!["tool".to_owned(), "nixpkgs".to_owned()];
+        let mut review = Review::new(&lock, false, &[stale.clone(), tool_nixpkgs]);
+        assert_eq!(review.candidates().len(), 2);
+        assert!(!review.candidates()[0].accepted);
+        assert_eq!(review.outcome().1, ["systems_2"]);
+
+        review.toggle(0);
+        assert_eq!(review.exclusions(), [stale]);
+        assert_eq!(review.outcome().1, ["nixpkgs_2", "systems_2"]);
+
+        review.set_all(false);
+        assert!(review.outcome().1.is_empty());
+        assert_eq!(review.exclusions().len(), 3);
+        // The lock which was reviewed is left as it was.
+        assert!(lock.get_node("nixpkgs_2").is_some());
+    }
+
+    #[test]
+    fn pruned() {
+        let lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "nixpkgs": {
+                        "locked": { "type": "github", "owner": "o", "repo": "nixpkgs" },
+                        "original": { "type": "github", "owner": "o", "repo": "nixpkgs" }
+                    },
+                    "tool": {
+                        "inputs": { "nixpkgs": ["nixpkgs"] },
+                        "locked": { "type": "github", "owner": "o", "repo": "tool" },
+                        "original": { "type": "github", "owner": "o", "repo": "tool" }
+                    },
+                    "root": { "inputs": { "nixpkgs": "nixpkgs", "tool": "tool" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let review = Review::new(&lock, false, &[]);
+        assert!(review.candidates().is_empty());
+    }
+}
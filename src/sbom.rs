@@ -0,0 +1,579 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::flake_lock::{LockFile, NodeEdge};
+use crate::flake_ref::FlakeRef;
+use crate::info::format_timestamp;
+use crate::nar_hash::{HashEncoding, NarHash};
+
+/// The standard of a software bill of materials.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SbomFormat {
+    #[default]
+    CycloneDx,
+    Spdx,
+}
+
+impl FromStr for SbomFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cyclonedx" => Ok(Self::CycloneDx),
+            "spdx" => Ok(Self::Spdx),
+            _ => Err(format!("expected `cyclonedx` or `spdx`, found `{s}`")),
+        }
+    }
+}
+
+/// Every locked input which is reachable from the root, and the nodes which
+/// each node depends on, independent of the format which they are written in.
+#[derive(Clone, Debug)]
+pub struct Sbom {
+    /// The name of the flake which the lock belongs to.
+    name: String,
+    /// When the document was created, in seconds since the Unix epoch.
+    created: u64,
+    root: String,
+    /// In the order that they are first reached from the root.
+    components: Vec<Component>,
+    /// The index of every node, with the indices of the nodes its inputs resolve to.
+    dependencies: Vec<(String, Vec<String>)>,
+}
+
+#[derive(Clone, Debug)]
+struct Component {
+    index: String,
+    /// The name of the first input which references the node.
+    name: String,
+    source: Option<Source>,
+    rev: Option<String>,
+    purl: Option<String>,
+    hash: Option<NarHash>,
+}
+
+/// Where the source of a locked input can be fetched from.
+#[derive(Clone, Debug)]
+enum Source {
+    /// The URL of a git repository, to be checked out at the revision.
+    Git(String),
+    /// The URL of an archive or file.
+    Download(String),
+}
+
+impl Sbom {
+    pub fn new(lock: &LockFile, name: &str, created: u64) -> Self {
+        let root = lock.root_index().to_owned();
+        let mut components = Vec::<Component>::new();
+        lock.walk_edges(&root, &mut |path, _, edge, target| {
+            if matches!(edge, NodeEdge::Follows(_))
+                || components.iter().any(|component| component.index == target)
+            {
+                return;
+            }
+            let Some(node) = lock.get_node(target) else {
+                return;
+            };
+            let Some(locked) = node.locked() else {
+                return;
+            };
+            components.push(Component {
+                index: target.to_owned(),
+                name: path.last().expect("a path to an input").clone(),
+                source: source(locked),
+                rev: locked.rev().map(str::to_owned),
+                purl: package_url(locked),
+                hash: locked
+                    .get_str("narHash")
+                    .and_then(|nar_hash| nar_hash.parse().ok()),
+            });
+        });
+
+        let dependencies = std::iter::once(&root)
+            .chain(components.iter().map(|component| &component.index))
+            .map(|index| {
+                let node = lock
+                    .get_node(index)
+                    .expect("a node to exist with this index");
+                let mut depends_on = Vec::<String>::new();
+                for (_, edge) in node.iter_edges() {
+                    let Some(target) = lock.resolve_edge(&edge) else {
+                        continue;
+                    };
+                    if !depends_on.contains(&target)
+                        && components.iter().any(|component| component.index == target)
+                    {
+                        depends_on.push(target);
+                    }
+                }
+                (index.clone(), depends_on)
+            })
+            .collect();
+
+        Self {
+            name: name.to_owned(),
+            created,
+            root,
+            components,
+            dependencies,
+        }
+    }
+
+    pub fn components(&self) -> usize {
+        self.components.len()
+    }
+
+    /// The document in the format, ready to be serialized as JSON.
+    pub fn to_value(&self, format: SbomFormat) -> serde_json::Value {
+        let value = match format {
+            SbomFormat::CycloneDx => serde_json::to_value(self.cyclonedx()),
+            SbomFormat::Spdx => serde_json::to_value(self.spdx()),
+        };
+        value.expect("the document to serialize")
+    }
+
+    fn created(&self) -> String {
+        format!("{}Z", format_timestamp(self.created).replacen(' ', "T", 1))
+    }
+
+    fn cyclonedx(&self) -> CycloneDx {
+        CycloneDx {
+            bom_format: "CycloneDX",
+            spec_version: "1.5",
+            version: 1,
+            metadata: CdxMetadata {
+                timestamp: self.created(),
+                tools: vec![CdxTool {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                }],
+                component: CdxComponent {
+                    kind: "application",
+                    bom_ref: self.root.clone(),
+                    name: self.name.clone(),
+                    version: None,
+                    purl: None,
+                    hashes: Vec::new(),
+                    external_references: Vec::new(),
+                },
+            },
+            components: self
+                .components
+                .iter()
+                .map(|component| CdxComponent {
+                    kind: "library",
+                    bom_ref: component.index.clone(),
+                    name: component.name.clone(),
+                    version: component.rev.clone(),
+                    purl: component.purl.clone(),
+                    hashes: component
+                        .hash
+                        .iter()
+                        .map(|hash| CdxHash {
+                            alg: "SHA-256",
+                            content: hash.encode(HashEncoding::Base16),
+                        })
+                        .collect(),
+                    external_references: component
+                        .source
+                        .iter()
+                        .map(|source| match source {
+                            Source::Git(url) => CdxReference {
+                                kind: "vcs",
+                                url: url.clone(),
+                            },
+                            Source::Download(url) => CdxReference {
+                                kind: "distribution",
+                                url: url.clone(),
+                            },
+                        })
+                        .collect(),
+                })
+                .collect(),
+            dependencies: self
+                .dependencies
+                .iter()
+                .map(|(index, depends_on)| CdxDependency {
+                    reference: index.clone(),
+                    depends_on: depends_on.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// The SPDX identifier of every node, where indices which would have the
+    /// same identifier are told apart by a number, in the order they are listed.
+    fn spdx_ids(&self) -> HashMap<&str, String> {
+        let mut taken = HashSet::from(["SPDXRef-DOCUMENT".to_owned()]);
+        std::iter::once(&self.root)
+            .chain(self.components.iter().map(|component| &component.index))
+            .map(|index| {
+                let mut id = spdx_id(index);
+                if taken.contains(&id) {
+                    id = (2..)
+                        .map(|n| format!("{id}-{n}"))
+                        .find(|id| !taken.contains(id))
+                        .expect("an unused identifier");
+                }
+                taken.insert(id.clone());
+                (index.as_str(), id)
+            })
+            .collect()
+    }
+
+    fn spdx(&self) -> Spdx {
+        let ids = self.spdx_ids();
+        let mut packages = vec![SpdxPackage {
+            id: ids[self.root.as_str()].clone(),
+            name: self.name.clone(),
+            version_info: None,
+            download_location: "NOASSERTION".to_owned(),
+            files_analyzed: false,
+            checksums: Vec::new(),
+            external_refs: Vec::new(),
+        }];
+        packages.extend(self.components.iter().map(|component| {
+            SpdxPackage {
+                id: ids[component.index.as_str()].clone(),
+                name: component.name.clone(),
+                version_info: component.rev.clone(),
+                download_location: match (&component.source, &component.rev) {
+                    (Some(Source::Git(url)), Some(rev)) => format!("git+{url}@{rev}"),
+                    (Some(Source::Git(url)), None) => format!("git+{url}"),
+                    (Some(Source::Download(url)), _) => url.clone(),
+                    (None, _) => "NOASSERTION".to_owned(),
+                },
+                files_analyzed: false,
+                checksums: component
+                    .hash
+                    .iter()
+                    .map(|hash| SpdxChecksum {
+                        algorithm: "SHA256",
+                        checksum_value: hash.encode(HashEncoding::Base16),
+                    })
+                    .collect(),
+                external_refs: component
+                    .purl
+                    .iter()
+                    .map(|purl| SpdxExternalRef {
+                        reference_category: "PACKAGE-MANAGER",
+                        reference_type: "purl",
+                        reference_locator: purl.clone(),
+                    })
+                    .collect(),
+            }
+        }));
+
+        let mut relationships = vec![SpdxRelationship {
+            element: "SPDXRef-DOCUMENT".to_owned(),
+            kind: "DESCRIBES",
+            related: ids[self.root.as_str()].clone(),
+        }];
+        for (index, depends_on) in &self.dependencies {
+            relationships.extend(depends_on.iter().map(|target| SpdxRelationship {
+                element: ids[index.as_str()].clone(),
+                kind: "DEPENDS_ON",
+                related: ids[target.as_str()].clone(),
+            }));
+        }
+
+        Spdx {
+            spdx_version: "SPDX-2.3",
+            data_license: "CC0-1.0",
+            id: "SPDXRef-DOCUMENT",
+            name: self.name.clone(),
+            document_namespace: format!(
+                "https://spdx.org/spdxdocs/{}-{}",
+                spdx_id(&self.name).trim_start_matches("SPDXRef-"),
+                self.created
+            ),
+            creation_info: SpdxCreationInfo {
+                created: self.created(),
+                creators: vec![format!(
+                    "Tool: {}-{}",
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION")
+                )],
+            },
+            packages,
+            relationships,
+        }
+    }
+}
+
+/// Where Nix fetches a locked input from, for the types which are fetched over the network.
+fn source(locked: FlakeRef) -> Option<Source> {
+    let forge = |default_host| {
+        let host = locked.get_str("host").unwrap_or(default_host);
+        let (owner, repo) = (locked.owner()?, locked.repo()?);
+        Some(Source::Git(format!("https://{host}/{owner}/{repo}")))
+    };
+    match locked.kind()? {
+        "github" => forge("github.com"),
+        "gitlab" => forge("gitlab.com"),
+        "sourcehut" => forge("git.sr.ht"),
+        "git" => Some(Source::Git(locked.url()?.to_owned())),
+        "tarball" | "file" => Some(Source::Download(locked.url()?.to_owned())),
+        _ => None,
+    }
+}
+
+/// The package URL of a locked input, for the forges which have a purl type.
+fn package_url(locked: FlakeRef) -> Option<String> {
+    let kind = match locked.kind()? {
+        kind @ ("github" | "gitlab") => kind,
+        _ => return None,
+    };
+    let mut purl = format!("pkg:{kind}/{}/{}", locked.owner()?, locked.repo()?);
+    if let Some(rev) = locked.rev() {
+        purl = format!("{purl}@{rev}");
+    }
+    Some(purl.to_ascii_lowercase())
+}
+
+/// An SPDX identifier for a node, which may only contain letters, digits, `.` and `-`.
+fn spdx_id(index: &str) -> String {
+    let index = index.replace(|c: char| !c.is_ascii_alphanumeric() && c != '.', "-");
+    format!("SPDXRef-{index}")
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CycloneDx {
+    bom_format: &'static str,
+    spec_version: &'static str,
+    version: u32,
+    metadata: CdxMetadata,
+    components: Vec<CdxComponent>,
+    dependencies: Vec<CdxDependency>,
+}
+
+#[derive(Serialize)]
+struct CdxMetadata {
+    timestamp: String,
+    tools: Vec<CdxTool>,
+    component: CdxComponent,
+}
+
+#[derive(Serialize)]
+struct CdxTool {
+    name: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CdxComponent {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(rename = "bom-ref")]
+    bom_ref: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    purl: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hashes: Vec<CdxHash>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    external_references: Vec<CdxReference>,
+}
+
+#[derive(Serialize)]
+struct CdxHash {
+    alg: &'static str,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct CdxReference {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    url: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CdxDependency {
+    #[serde(rename = "ref")]
+    reference: String,
+    depends_on: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Spdx {
+    spdx_version: &'static str,
+    data_license: &'static str,
+    #[serde(rename = "SPDXID")]
+    id: &'static str,
+    name: String,
+    document_namespace: String,
+    creation_info: SpdxCreationInfo,
+    packages: Vec<SpdxPackage>,
+    relationships: Vec<SpdxRelationship>,
+}
+
+#[derive(Serialize)]
+struct SpdxCreationInfo {
+    created: String,
+    creators: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxPackage {
+    #[serde(rename = "SPDXID")]
+    id: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version_info: Option<String>,
+    download_location: String,
+    files_analyzed: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    checksums: Vec<SpdxChecksum>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    external_refs: Vec<SpdxExternalRef>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxChecksum {
+    algorithm: &'static str,
+    checksum_value: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxExternalRef {
+    reference_category: &'static str,
+    reference_type: &'static str,
+    reference_locator: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxRelationship {
+    #[serde(rename = "spdxElementId")]
+    element: String,
+    #[serde(rename = "relationshipType")]
+    kind: &'static str,
+    #[serde(rename = "relatedSpdxElement")]
+    related: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{Sbom, SbomFormat};
+    use crate::flake_lock::LockFile;
+
+    #[test]
+    fn documents() {
+        let lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "nixpkgs": {
+                        "locked": { "type": "github", "owner": "NixOS", "repo": "nixpkgs", "rev": "abc", "narHash": "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=" },
+                        "original": { "type": "github", "owner": "NixOS", "repo": "nixpkgs" }
+                    },
+                    "tool_2": {
+                        "inputs": { "nixpkgs": ["nixpkgs"] },
+                        "locked": { "type": "path", "path": "/tool" },
+                        "original": { "type": "path", "path": "/tool" }
+                    },
+                    "root": { "inputs": { "nixpkgs": "nixpkgs", "tool": "tool_2" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let sbom = Sbom::new(&lock, "flake", 0);
+        assert_eq!(sbom.components(), 2);
+
+        let cyclonedx = sbom.to_value(SbomFormat::CycloneDx);
+        assert_eq!(
+            cyclonedx["components"][0],
+            json!({
+                "type": "library",
+                "bom-ref": "nixpkgs",
+                "name": "nixpkgs",
+                "version": "abc",
+                "purl": "pkg:github/nixos/nixpkgs@abc",
+                "hashes": [{ "alg": "SHA-256", "content": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855" }],
+                "externalReferences": [{ "type": "vcs", "url": "https://github.com/NixOS/nixpkgs" }]
+            })
+        );
+        assert_eq!(
+            cyclonedx["dependencies"],
+            json!([
+                { "ref": "root", "dependsOn": ["nixpkgs", "tool_2"] },
+                { "ref": "nixpkgs", "dependsOn": [] },
+                { "ref": "tool_2", "dependsOn": ["nixpkgs"] }
+            ])
+        );
+
+        let spdx = sbom.to_value(SbomFormat::Spdx);
+        assert_eq!(spdx["creationInfo"]["created"], "1970-01-01T00:00:00Z");
+        assert_eq!(
+            spdx["packages"][1]["downloadLocation"],
+            "git+https://github.com/NixOS/nixpkgs@abc"
+        );
+        assert_eq!(spdx["packages"][2]["SPDXID"], "SPDXRef-tool-2");
+        assert_eq!(spdx["packages"][2]["downloadLocation"], "NOASSERTION");
+        assert_eq!(
+            spdx["relationships"][3],
+            json!({
+                "spdxElementId": "SPDXRef-tool-2",
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": "SPDXRef-nixpkgs"
+            })
+        );
+    }
+
+    #[test]
+    fn spdx_ids() {
+        let lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "nixpkgs-2": {
+                        "locked": { "type": "path", "path": "/a" },
+                        "original": { "type": "path", "path": "/a" }
+                    },
+                    "nixpkgs_2": {
+                        "locked": { "type": "path", "path": "/b" },
+                        "original": { "type": "path", "path": "/b" }
+                    },
+                    "DOCUMENT": {
+                        "locked": { "type": "path", "path": "/c" },
+                        "original": { "type": "path", "path": "/c" }
+                    },
+                    "root": { "inputs": { "a": "nixpkgs-2", "b": "nixpkgs_2", "c": "DOCUMENT" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let spdx = Sbom::new(&lock, "flake", 0).to_value(SbomFormat::Spdx);
+        let ids = spdx["packages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|package| package["SPDXID"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            [
+                "SPDXRef-root",
+                "SPDXRef-nixpkgs-2",
+                "SPDXRef-nixpkgs-2-2",
+                "SPDXRef-DOCUMENT-2"
+            ]
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use owo_colors::OwoColorize;
+use serde::Serialize;
+
+use crate::flake_lock::{LockFile, NodeEdge};
+use crate::{format_args_colored, FlakeNodeVisits};
+
+/// A summary of the shape of a lock, and of where its duplicate nodes come from.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LockStats {
+    nodes: usize,
+    reachable: usize,
+    orphans: usize,
+    follows_edges: usize,
+    indexed_edges: usize,
+    /// The most inputs taken from the root to reach a node.
+    max_depth: usize,
+    /// How many reachable nodes have each number of parent nodes.
+    fan_in: Vec<Bucket>,
+    /// How many reachable nodes have each number of inputs.
+    fan_out: Vec<Bucket>,
+    /// The distinct flake references which reachable nodes were declared as.
+    sources: usize,
+    /// The average number of reachable nodes for each source.
+    duplication: f64,
+    /// The sources of several reachable nodes, most copies first.
+    duplicated_sources: Vec<SourceCopies>,
+    /// The inputs of the root which bring in the most extra copies of sources.
+    top_duplicators: Vec<Duplicator>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bucket {
+    pub degree: usize,
+    pub nodes: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceCopies {
+    pub source: String,
+    /// The copy which the others would be replaced by, preferring inputs of the root.
+    pub kept: String,
+    pub copies: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Duplicator {
+    pub input: String,
+    /// The extra copies of sources which the input references by index.
+    pub extra_copies: Vec<String>,
+}
+
+impl LockStats {
+    /// Summarize the lock, keeping only the `top` inputs which cause the most duplicates.
+    pub fn new(lock: &LockFile, top: usize) -> Self {
+        let root_index = lock.root_index();
+        let visits = FlakeNodeVisits::count_from_index(lock, root_index);
+        let mut reachable = lock
+            .node_indices()
+            .filter(|&index| visits.get(index).is_some_and(|&count| count > 0))
+            .collect::<Vec<_>>();
+        reachable.sort_unstable();
+
+        let (mut follows_edges, mut indexed_edges) = (0, 0);
+        let mut parents = HashMap::<String, BTreeSet<&str>>::new();
+        let mut fan_out = BTreeMap::<usize, usize>::new();
+        for index in lock.node_indices() {
+            let node = lock
+                .get_node(index)
+                .expect("a node to exist with this index");
+            let is_reachable = reachable.contains(&index);
+            let mut inputs = 0;
+            for (_, edge) in node.iter_edges() {
+                inputs += 1;
+                match &*edge {
+                    NodeEdge::Follows(_) => follows_edges += 1,
+                    NodeEdge::Indexed(_) => indexed_edges += 1,
+                }
+                // Orphans are not counted as the parents of the nodes they reference.
+                if let Some(target) = lock.resolve_edge(&edge).filter(|_| is_reachable) {
+                    parents.entry(target).or_default().insert(index);
+                }
+            }
+            if is_reachable {
+                *fan_out.entry(inputs).or_default() += 1;
+            }
+        }
+        let mut fan_in = BTreeMap::<usize, usize>::new();
+        for &index in reachable.iter().filter(|&&index| index != root_index) {
+            *fan_in
+                .entry(parents.get(index).map_or(0, BTreeSet::len))
+                .or_default() += 1;
+        }
+
+        let root_targets = lock
+            .root()
+            .map(|root| {
+                root.iter_edges()
+                    .filter_map(|(_, edge)| lock.resolve_edge(&edge))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let mut copies = BTreeMap::<String, Vec<&str>>::new();
+        for &index in &reachable {
+            let source = lock
+                .get_node(index)
+                .and_then(|node| node.original().map(|original| original.to_string()));
+            if let Some(source) = source {
+                copies.entry(source).or_default().push(index);
+            }
+        }
+        let sources = copies.len();
+        let locked_nodes = copies.values().map(Vec::len).sum::<usize>();
+        let mut duplicated_sources = copies
+            .into_iter()
+            .filter(|(_, copies)| copies.len() > 1)
+            .map(|(source, copies)| {
+                // The most referenced copy is kept, unless the root has one.
+                let kept = copies
+                    .iter()
+                    .copied()
+                    .max_by_key(|&index| {
+                        (
+                            root_targets.iter().any(|target| target == index),
+                            visits[index],
+                            std::cmp::Reverse(index),
+                        )
+                    })
+                    .expect("a source to have copies");
+                SourceCopies {
+                    source,
+                    kept: kept.to_owned(),
+                    copies: copies.into_iter().map(str::to_owned).collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+        duplicated_sources.sort_by(|a, b| {
+            b.copies
+                .len()
+                .cmp(&a.copies.len())
+                .then_with(|| a.source.cmp(&b.source))
+        });
+
+        let max_depth = depth(lock, root_index, &mut HashMap::new(), &mut Vec::new());
+        let is_extra = |target: &str| {
+            duplicated_sources.iter().any(|source| {
+                source.kept != target && source.copies.iter().any(|copy| copy == target)
+            })
+        };
+        let mut extra_copies = BTreeMap::<String, BTreeSet<String>>::new();
+        for (input, edge) in lock.root().iter().flat_map(|root| root.iter_edges()) {
+            let Some(start) = lock.resolve_edge(&edge) else {
+                continue;
+            };
+            let mut found = BTreeSet::new();
+            if matches!(*edge, NodeEdge::Indexed(_)) && is_extra(&start) {
+                found.insert(start.clone());
+            }
+            lock.walk_edges(&start, &mut |_, _, edge, target| {
+                if matches!(edge, NodeEdge::Indexed(_)) && is_extra(target) {
+                    found.insert(target.to_owned());
+                }
+            });
+            if !found.is_empty() {
+                extra_copies.insert(input.to_owned(), found);
+            }
+        }
+        let mut top_duplicators = extra_copies
+            .into_iter()
+            .map(|(input, extra_copies)| Duplicator {
+                input,
+                extra_copies: extra_copies.into_iter().collect(),
+            })
+            .collect::<Vec<_>>();
+        top_duplicators.sort_by_key(|duplicator| std::cmp::Reverse(duplicator.extra_copies.len()));
+        top_duplicators.truncate(top);
+
+        let buckets = |counts: BTreeMap<usize, usize>| {
+            counts
+                .into_iter()
+                .map(|(degree, nodes)| Bucket { degree, nodes })
+                .collect()
+        };
+        Self {
+            nodes: lock.node_indices().count(),
+            reachable: reachable.len(),
+            orphans: lock.node_indices().count() - reachable.len(),
+            follows_edges,
+            indexed_edges,
+            max_depth,
+            fan_in: buckets(fan_in),
+            fan_out: buckets(fan_out),
+            sources,
+            duplication: if sources == 0 {
+                1.0
+            } else {
+                locked_nodes as f64 / sources as f64
+            },
+            duplicated_sources,
+            top_duplicators,
+        }
+    }
+
+    /// How many reachable nodes could be removed if every source had one copy.
+    pub fn extra_copies(&self) -> usize {
+        self.duplicated_sources
+            .iter()
+            .map(|source| source.copies.len() - 1)
+            .sum()
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.nodes
+    }
+}
+
+impl std::fmt::Display for LockStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = |label: &str| format!("{label:<12}");
+        f.write_fmt(format_args_colored!(
+            :bold (label("Nodes:")) :bold :bright_cyan (self.nodes),
+            :dimmed .("({} reachable, {} orphans)", self.reachable, self.orphans);
+            :bold (label("Edges:")) :bold :bright_cyan (self.follows_edges + self.indexed_edges),
+            :dimmed .("({} follows, {} indexed)", self.follows_edges, self.indexed_edges);
+            :bold (label("Max depth:")) :bold :bright_cyan (self.max_depth);
+            :bold (label("Sources:")) :bold :bright_cyan (self.sources),
+            :dimmed .("({:.2} nodes per source, {} extra copies)", self.duplication, self.extra_copies());
+        ))?;
+
+        for (title, header, buckets) in [
+            ("Fan-in:", "PARENTS", &self.fan_in),
+            ("Fan-out:", "INPUTS", &self.fan_out),
+        ] {
+            let rows = buckets
+                .iter()
+                .map(|bucket| [bucket.degree.to_string(), bucket.nodes.to_string()])
+                .collect::<Vec<_>>();
+            f.write_fmt(format_args_colored!(; :bold :bright_magenta (title);))?;
+            write_table(f, [header, "NODES"], &rows)?;
+        }
+
+        if !self.duplicated_sources.is_empty() {
+            let rows = self
+                .duplicated_sources
+                .iter()
+                .map(|source| {
+                    [
+                        source.source.clone(),
+                        source.copies.len().to_string(),
+                        source.kept.clone(),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            f.write_fmt(format_args_colored!(; :bold :bright_magenta "Duplicated sources:";))?;
+            write_table(f, ["SOURCE", "COPIES", "KEPT"], &rows)?;
+        }
+
+        if !self.top_duplicators.is_empty() {
+            let rows = self
+                .top_duplicators
+                .iter()
+                .map(|duplicator| {
+                    [
+                        duplicator.input.clone(),
+                        duplicator.extra_copies.len().to_string(),
+                        duplicator.extra_copies.join(", "),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            f.write_fmt(
+                format_args_colored!(; :bold :bright_magenta "Inputs bringing in the most duplicates:";),
+            )?;
+            write_table(f, ["INPUT", "EXTRA", "NODES"], &rows)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `rows` beneath `headers`, with the first column as a label,
+/// the second as a count, and the rest dimmed.
+fn write_table<const N: usize>(
+    f: &mut std::fmt::Formatter<'_>,
+    headers: [&str; N],
+    rows: &[[String; N]],
+) -> std::fmt::Result {
+    let mut widths = headers.map(str::len);
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    // The last column is not padded, so that lines do not end with spaces,
+    // unless it is the count, which is aligned to the right.
+    if N > 2 {
+        widths[N - 1] = 0;
+    }
+    for (i, header) in headers.iter().enumerate() {
+        let sep = if i + 1 == N { "\n" } else { " " };
+        if i == 1 {
+            f.write_fmt(format_args_colored!(:bold .("{header:>0$}", widths[i]) (sep)))?;
+        } else {
+            f.write_fmt(format_args_colored!(:bold .("{header:0$}", widths[i]) (sep)))?;
+        }
+    }
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            let sep = if i + 1 == N { "\n" } else { " " };
+            match i {
+                0 => f.write_fmt(format_args_colored!(.("{cell:0$}", widths[i]) (sep)))?,
+                1 => f.write_fmt(
+                    format_args_colored!(:bold :bright_cyan .("{cell:>0$}", widths[i]) (sep)),
+                )?,
+                _ => f.write_fmt(format_args_colored!(:dimmed .("{cell:0$}", widths[i]) (sep)))?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The most inputs taken from `index` to reach a node, without passing through
+/// a node twice, remembering the depth of every node below it in `depths`.
+fn depth(
+    lock: &LockFile,
+    index: &str,
+    depths: &mut HashMap<String, usize>,
+    stack: &mut Vec<String>,
+) -> usize {
+    if let Some(&depth) = depths.get(index) {
+        return depth;
+    }
+    let targets = lock
+        .get_node(index)
+        .map(|node| {
+            node.iter_edges()
+                .filter_map(|(_, edge)| lock.resolve_edge(&edge))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    stack.push(index.to_owned());
+    let max = targets
+        .iter()
+        .map(|target| {
+            if stack.contains(target) {
+                1
+            } else {
+                1 + depth(lock, target, depths, stack)
+            }
+        })
+        .max()
+        .unwrap_or(0);
+    stack.pop();
+    depths.insert(index.to_owned(), max);
+    max
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LockStats;
+    use crate::flake_lock::LockFile;
+
+    #[test]
+    fn summary() {
+        let lock: LockFile = serde_json::from_str(
+            r#"{
+                "nodes": {
+                    "nixpkgs": {
+                        "locked": { "type": "github", "owner": "o", "repo": "nixpkgs" },
+                        "original": { "type": "github", "owner": "o", "repo": "nixpkgs" }
+                    },
+                    "nixpkgs_2": {
+                        "locked": { "type": "github", "owner": "o", "repo": "nixpkgs" },
+                        "original": { "type": "github", "owner": "o", "repo": "nixpkgs" }
+                    },
+                    "old": {
+                        "inputs": { "utils": "utils" },
+                        "locked": { "type": "github", "owner": "o", "repo": "old" },
+                        "original": { "type": "github", "owner": "o", "repo": "old" }
+                    },
+                    "tool": {
+                        "inputs": { "nixpkgs": "nixpkgs_2", "utils": ["utils"] },
+                        "locked": { "type": "github", "owner": "o", "repo": "tool" },
+                        "original": { "type": "github", "owner": "o", "repo": "tool" }
+                    },
+                    "utils": {
+                        "locked": { "type": "github", "owner": "o", "repo": "utils" },
+                        "original": { "type": "github", "owner": "o", "repo": "utils" }
+                    },
+                    "root": { "inputs": { "nixpkgs": "nixpkgs", "tool": "tool", "utils": "utils" } }
+                },
+                "root": "root",
+                "version": 7
+            }"#,
+        )
+        .unwrap();
+        let stats = LockStats::new(&lock, 5);
+        assert_eq!((stats.nodes, stats.reachable, stats.orphans), (6, 5, 1));
+        assert_eq!((stats.follows_edges, stats.indexed_edges), (1, 5));
+        assert_eq!(stats.max_depth, 2);
+        assert_eq!(stats.sources, 3);
+        assert_eq!(stats.extra_copies(), 1);
+        assert_eq!(stats.duplicated_sources[0].kept, "nixpkgs");
+        assert_eq!(stats.top_duplicators[0].input, "tool");
+        assert_eq!(stats.top_duplicators[0].extra_copies, ["nixpkgs_2"]);
+        let fan_in = stats
+            .fan_in
+            .iter()
+            .map(|bucket| (bucket.degree, bucket.nodes))
+            .collect::<Vec<_>>();
+        // The orphan is not a parent of `utils`.
+        assert_eq!(fan_in, [(1, 3), (2, 1)]);
+    }
+}
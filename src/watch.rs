@@ -0,0 +1,59 @@
+use std::io;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+
+/// How long to wait for more events after the first, so that a burst of writes
+/// is handled as a single change.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Block forever, calling `op` with the contents of the file at `path`
+/// once initially and then whenever those contents change.
+///
+/// The parent directory is watched rather than the file itself, because
+/// Nix replaces the lock file by renaming a new file over it.
+///
+/// If `op` writes to the file, it should return what was written,
+/// so that the change it has made does not trigger it again.
+pub fn watch_file(path: &Path, mut op: impl FnMut(&[u8]) -> Option<Vec<u8>>) -> notify::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| notify::Error::generic("the path to watch must name a file"))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(parent, RecursiveMode::NonRecursive)?;
+
+    let mut last_contents = None;
+    let mut handle_change = |last_contents: &mut Option<Vec<u8>>| -> io::Result<()> {
+        let contents = match std::fs::read(path) {
+            Ok(contents) => contents,
+            // The file is being replaced, there will be another event.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if last_contents.as_ref() != Some(&contents) {
+            *last_contents = Some(op(&contents).unwrap_or(contents));
+        }
+        Ok(())
+    };
+
+    handle_change(&mut last_contents)?;
+    loop {
+        let event = rx
+            .recv()
+            .map_err(|_| notify::Error::generic("the watcher hung up"))??;
+        if !event.paths.iter().any(|p| p.file_name() == Some(file_name)) {
+            continue;
+        }
+        std::thread::sleep(DEBOUNCE);
+        while rx.try_recv().is_ok() {}
+        handle_change(&mut last_contents)?;
+    }
+}